@@ -21,7 +21,7 @@ fn main() {
         println!("{:#?}", price);
         let price = conn.get_prices_tomorrow(h);
         println!("{:#?}", price);
-        let history = conn.get_consuption(h, TimeResolution::Daily, 10);
+        let history = conn.get_consumption(h, TimeResolution::Daily, 10);
         println!("{:#?}", history);
         let delivery = conn.get_production(h, TimeResolution::Hourly, 24);
         println!("{:#?}", delivery);
@@ -0,0 +1,30 @@
+use tibber::*;
+
+#[tokio::main]
+async fn main() {
+    let api_token = match std::env::var("TIBBER_API_TOKEN") {
+        Ok(v) => v,
+        _ => {
+            panic!("Set TIBBER_API_TOKEN environmental variable")
+        }
+    };
+
+    let conn = AsyncTibberSession::new(api_token);
+    let user = conn.get_user().await.unwrap();
+    println!("{:#?}", user);
+
+    for h in &user.homes {
+        let home = conn.get_home(h).await;
+        println!("{:#?}", home);
+        let price = conn.get_current_price(h).await;
+        println!("{:#?}", price);
+        let price = conn.get_prices_today(h).await;
+        println!("{:#?}", price);
+        let price = conn.get_prices_tomorrow(h).await;
+        println!("{:#?}", price);
+        let history = conn.get_consumption(h, TimeResolution::Daily, 10).await;
+        println!("{:#?}", history);
+        let delivery = conn.get_production(h, TimeResolution::Hourly, 24).await;
+        println!("{:#?}", delivery);
+    }
+}
@@ -28,7 +28,7 @@ fn main() {
     let last = 24 * args[0].parse::<u32>().unwrap_or(10);
 
     let consumption = conn
-        .get_consuption(&user.homes[0], TimeResolution::Hourly, last)
+        .get_consumption(&user.homes[0], TimeResolution::Hourly, last)
         .unwrap_or_default();
 
     let mut hist = HashMap::new();
@@ -0,0 +1,35 @@
+use tibber::*;
+
+/// Print real time measurements for the first home with a power meter
+fn main() {
+    let api_token = match std::env::var("TIBBER_API_TOKEN") {
+        Ok(v) => v,
+        _ => {
+            panic!("Set TIBBER_API_TOKEN environmental variable")
+        }
+    };
+
+    let conn = TibberSession::new(api_token);
+    let user = conn.get_user().unwrap();
+
+    for h in &user.homes {
+        let stream = match conn.subscribe_live(h) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("{:?}: {}", h, e);
+                continue;
+            }
+        };
+        for m in stream {
+            match m {
+                Ok(m) => println!(
+                    "{} {} W, {:.3} kWh this hour",
+                    m.timestamp, m.power, m.accumulated_consumption_last_hour
+                ),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+        return;
+    }
+    println!("No homes with real time consumption found");
+}
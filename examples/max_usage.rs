@@ -1,6 +1,8 @@
+use chrono::prelude::*;
 use tibber::*;
 
-/// Find max hourly usages last n days
+/// Find the highest hourly usages this month, on different days, as used
+/// for the capacity step of the grid fee
 fn main() {
     let api_token = match std::env::var("TIBBER_API_TOKEN") {
         Ok(v) => v,
@@ -8,7 +10,6 @@ fn main() {
             panic!("Set TIBBER_API_TOKEN environmental variable")
         }
     };
-    let args: Vec<String> = std::env::args().skip(1).collect();
 
     let conn = TibberSession::new(api_token);
     let user = conn.get_user().unwrap();
@@ -17,34 +18,17 @@ fn main() {
         return;
     }
 
-    if args.is_empty() {
-        println!("usage: histogram n  ( n last days )");
-        return;
-    }
-
-    let last = 24 * args[0].parse::<u32>().unwrap_or(10);
+    let now = Local::now();
+    let last = 24 * now.day();
 
-    let mut consumption = conn
-        .get_consuption(&user.homes[0], TimeResolution::Hourly, last)
+    let consumption = conn
+        .get_consumption(&user.homes[0], TimeResolution::Hourly, last)
         .unwrap_or_default();
 
-    consumption.sort_by(|a, b| b.energy.partial_cmp(&a.energy).unwrap());
-
-    let max: Vec<Consumption> = consumption.into_iter().take(3).collect();
-
-    let sum: f64 = max
-        .iter()
-        .map(|c| {
-            if let EnergyUnits::kWh(e) = c.energy {
-                e
-            } else {
-                0.0
-            }
-        })
-        .sum();
+    let max = capacity_tariff::top_peaks(&consumption, now.year(), now.month());
 
     if !max.is_empty() {
-        println!("Max usage: {}", sum / max.len() as f64);
+        println!("Max usage: {}", capacity_tariff::average(&max));
     }
 
     println!("Max {:#?}", max);
@@ -0,0 +1,43 @@
+use chrono::prelude::*;
+use tibber::*;
+
+/// Find the cheapest consecutive hours to run a load within the next n hours
+fn main() {
+    let api_token = match std::env::var("TIBBER_API_TOKEN") {
+        Ok(v) => v,
+        _ => {
+            panic!("Set TIBBER_API_TOKEN environmental variable")
+        }
+    };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        println!("usage: window hours kwh_per_hour [n]  ( within n next hours )");
+        return;
+    }
+    let hours = args[0].parse::<usize>().unwrap_or(3);
+    let kwh = args[1].parse::<f64>().unwrap_or(1.0);
+    let within = args
+        .get(2)
+        .and_then(|a| a.parse::<i64>().ok())
+        .unwrap_or(24);
+
+    let conn = TibberSession::new(api_token);
+    let user = conn.get_user().unwrap();
+    if user.homes.is_empty() {
+        println!("No homes found");
+        return;
+    }
+
+    let mut prices = conn.get_prices_today(&user.homes[0]).unwrap_or_default();
+    prices.extend(conn.get_prices_tomorrow(&user.homes[0]).unwrap_or_default());
+
+    let now: DateTime<FixedOffset> = Local::now().into();
+    let deadline = now + chrono::Duration::hours(within);
+    let profile = vec![kwh; hours];
+    for w in planner::cheapest_windows(&prices, &profile, now, deadline, 3) {
+        println!(
+            "{} - {} {:.2} {}",
+            w.starts_at, w.ends_at, w.cost, w.currency
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Configuration of sessions, see [`TibberSession::builder()`]
+use crate::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub(crate) const DEFAULT_ENDPOINT: &str = "https://api.tibber.com/v1-beta/gql/";
+pub(crate) const DEFAULT_WEBSOCKET_URL: &str =
+    "wss://websocket-api.tibber.com/v1-beta/gql/subscriptions";
+pub(crate) const DEFAULT_USER_AGENT: &str = "graphql-rust/0.14.0";
+
+/// Builder for [`TibberSession`] (and [`AsyncTibberSession`] with the `async` feature)
+///
+/// The HTTP client is created once when the session is built, and reused for
+/// all calls made through the session.
+pub struct TibberSessionBuilder {
+    authentication: String,
+    endpoint: String,
+    websocket_url: String,
+    user_agent: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy: Option<String>,
+    lenient: bool,
+    cache_dir: Option<PathBuf>,
+    #[cfg(feature = "reqwest")]
+    client: Option<::reqwest::blocking::Client>,
+    #[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+    agent: Option<ureq_crate::Agent>,
+    #[cfg(feature = "async")]
+    async_client: Option<::reqwest::Client>,
+}
+
+impl TibberSessionBuilder {
+    pub(crate) fn new(authentication: String) -> Self {
+        TibberSessionBuilder {
+            authentication,
+            endpoint: DEFAULT_ENDPOINT.to_owned(),
+            websocket_url: DEFAULT_WEBSOCKET_URL.to_owned(),
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            timeout: None,
+            connect_timeout: None,
+            proxy: None,
+            lenient: false,
+            cache_dir: None,
+            #[cfg(feature = "reqwest")]
+            client: None,
+            #[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+            agent: None,
+            #[cfg(feature = "async")]
+            async_client: None,
+        }
+    }
+
+    /// URL of the GraphQL endpoint, defaults to the Tibber production API
+    pub fn endpoint(mut self, url: impl Into<String>) -> Self {
+        self.endpoint = url.into();
+        self
+    }
+
+    /// URL used for real time subscriptions
+    pub fn websocket_url(mut self, url: impl Into<String>) -> Self {
+        self.websocket_url = url.into();
+        self
+    }
+
+    /// User agent sent with every request. A client or agent given to the
+    /// builder keeps its own user agent for API requests.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Timeout for a complete request, otherwise the HTTP client default is used
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for establishing a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send all requests through a proxy, e.g. `http://proxy:3128`
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Keep consumption and production intervals with missing cost, e.g.
+    /// hours not invoiced yet, instead of leaving them out
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Cache responses in `dir`, for as long as they are known not to change.
+    ///
+    /// Covers the current price (until the next interval), todays prices
+    /// (until midnight) and tomorrows prices (until published, and then until
+    /// they become todays prices). The directory is created when needed, and
+    /// may be shared by sessions with different tokens or endpoints.
+    ///
+    /// Price ranges, consumption and production are never cached, not even
+    /// for past days that won't change. They are paged by cursor, and a page
+    /// reaching the present would be missing the intervals that come later.
+    /// The `store` feature keeps such history on disk instead.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Use an existing client. Timeouts, proxy and user agent set on the
+    /// builder are ignored for API requests, as they are properties of the
+    /// client. The user agent is still sent when opening live streams.
+    #[cfg(feature = "reqwest")]
+    pub fn client(mut self, client: ::reqwest::blocking::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Use an existing agent. Timeouts, proxy and user agent set on the
+    /// builder are ignored for API requests, as they are properties of the
+    /// agent. The user agent is still sent when opening live streams.
+    #[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+    pub fn agent(mut self, agent: ureq_crate::Agent) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    /// Use an existing async client for [`Self::build_async()`]. Timeouts,
+    /// proxy and user agent set on the builder are ignored, as they are
+    /// properties of the client.
+    #[cfg(feature = "async")]
+    pub fn async_client(mut self, client: ::reqwest::Client) -> Self {
+        self.async_client = Some(client);
+        self
+    }
+
+    /// Create the session, using the default transport
+    #[cfg(feature = "reqwest")]
+    pub fn build(mut self) -> Result<TibberSession, Error> {
+        let client = match self.client.take() {
+            Some(c) => c,
+            None => {
+                let mut builder =
+                    ::reqwest::blocking::Client::builder().user_agent(self.user_agent.as_str());
+                if let Some(t) = self.timeout {
+                    builder = builder.timeout(t);
+                }
+                if let Some(t) = self.connect_timeout {
+                    builder = builder.connect_timeout(t);
+                }
+                if let Some(p) = &self.proxy {
+                    builder = builder.proxy(::reqwest::Proxy::all(p.as_str())?);
+                }
+                builder.build()?
+            }
+        };
+        let transport = ReqwestTransport::new(client, self.endpoint.as_str());
+        Ok(self.build_with_transport(transport))
+    }
+
+    /// Create the session, using the default transport
+    #[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+    pub fn build(mut self) -> Result<TibberSession, Error> {
+        let agent = match self.agent.take() {
+            Some(a) => a,
+            None => {
+                let mut builder = ureq_crate::AgentBuilder::new().user_agent(&self.user_agent);
+                if let Some(t) = self.timeout {
+                    builder = builder.timeout(t);
+                }
+                if let Some(t) = self.connect_timeout {
+                    builder = builder.timeout_connect(t);
+                }
+                if let Some(p) = &self.proxy {
+                    builder = builder.proxy(
+                        ureq_crate::Proxy::new(p).map_err(|e| Error::Transport(Box::new(e)))?,
+                    );
+                }
+                builder.build()
+            }
+        };
+        let transport = UreqTransport::new(agent, self.endpoint.as_str());
+        Ok(self.build_with_transport(transport))
+    }
+
+    fn cache(&self) -> Option<cache::Cache> {
+        let dir = self.cache_dir.clone()?;
+        Some(cache::Cache::new(dir, &self.endpoint, &self.authentication))
+    }
+
+    /// Create a session that sends all requests through `transport`. Only the
+    /// token, user agent, websocket URL, lenient mode and cache are taken from
+    /// the builder, the rest is up to the transport.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> TibberSession<T> {
+        let cache = self.cache();
+        TibberSession {
+            authentication: self.authentication,
+            transport: Arc::new(transport),
+            websocket_url: self.websocket_url,
+            user_agent: self.user_agent,
+            partial: None,
+            lenient: self.lenient,
+            cache,
+        }
+    }
+
+    /// Create an async session
+    #[cfg(feature = "async")]
+    pub fn build_async(mut self) -> Result<AsyncTibberSession, Error> {
+        let client = match self.async_client.take() {
+            Some(c) => c,
+            None => {
+                let mut builder = ::reqwest::Client::builder().user_agent(self.user_agent.as_str());
+                if let Some(t) = self.timeout {
+                    builder = builder.timeout(t);
+                }
+                if let Some(t) = self.connect_timeout {
+                    builder = builder.connect_timeout(t);
+                }
+                if let Some(p) = &self.proxy {
+                    builder = builder.proxy(::reqwest::Proxy::all(p.as_str())?);
+                }
+                builder.build()?
+            }
+        };
+        let transport = AsyncReqwestTransport::new(client, self.endpoint.as_str());
+        Ok(self.build_async_with_transport(transport))
+    }
+
+    /// Create an async session that sends all requests through `transport`.
+    /// Only the token, lenient mode and cache are taken from the builder, the
+    /// rest is up to the transport.
+    #[cfg(feature = "async")]
+    pub fn build_async_with_transport<T: AsyncTransport>(
+        self,
+        transport: T,
+    ) -> AsyncTibberSession<T> {
+        let cache = self.cache();
+        AsyncTibberSession {
+            authentication: self.authentication,
+            transport: Arc::new(transport),
+            partial: None,
+            lenient: self.lenient,
+            cache,
+        }
+    }
+}
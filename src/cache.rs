@@ -0,0 +1,247 @@
+//! Opt-in on-disk cache for responses that change on a known schedule, see
+//! [`TibberSessionBuilder::cache_dir()`]
+use crate::*;
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// How long to wait before asking again when expected data is missing
+const RETRY: Duration = Duration::minutes(10);
+
+/// Hour (UTC) from which tomorrows prices may be published. They appear
+/// around 13:00 CET, which is 11:00 UTC in summer and 12:00 UTC in winter.
+const PUBLISH_HOUR_UTC: u32 = 11;
+
+/// A response and when it expires, as kept on disk
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Entry {
+    expires: String,
+    response: String,
+}
+
+/// Directory with one file pr query, home, endpoint and token
+#[derive(Debug, Clone)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl Cache {
+    /// Cache in `dir` for requests with `token` to `endpoint`. Neither is
+    /// stored in the clear, only a hash of them in the file names.
+    pub fn new(dir: PathBuf, endpoint: &str, token: &str) -> Self {
+        Cache {
+            dir,
+            key: format!("{:016x}", fnv1a(&[endpoint, token])),
+        }
+    }
+
+    fn path(&self, operation: &str, home_id: &HomeId) -> PathBuf {
+        let home: String = home_id
+            .0
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir
+            .join(format!("{}-{}-{}.json", operation, home, self.key))
+    }
+
+    /// Result from a cached response that has not expired yet. Anything
+    /// unreadable is treated as a miss.
+    pub fn load<D: DeserializeOwned, R>(
+        &self,
+        operation: &str,
+        home_id: &HomeId,
+        convert: impl FnOnce(D) -> Result<R, Error>,
+    ) -> Option<R> {
+        decode(&self.read(operation, home_id)?, convert)
+    }
+
+    /// Cached response body that has not expired yet
+    pub fn read(&self, operation: &str, home_id: &HomeId) -> Option<String> {
+        let text = std::fs::read_to_string(self.path(operation, home_id)).ok()?;
+        let entry: Entry = serde_json::from_str(&text).ok()?;
+        let expires = DateTime::parse_from_rfc3339(&entry.expires).ok()?;
+        if expires <= Utc::now() {
+            return None;
+        }
+        Some(entry.response)
+    }
+
+    /// Convert a fresh response, and keep it until `expires` says so. Only
+    /// responses without errors are kept, and failing to write the cache
+    /// does not fail the call.
+    pub fn store<D: DeserializeOwned, R>(
+        &self,
+        operation: &str,
+        home_id: &HomeId,
+        response: &str,
+        collector: Option<&Mutex<Vec<GraphQLError>>>,
+        convert: impl FnOnce(D) -> Result<R, Error>,
+        expires: impl FnOnce(&R, DateTime<Utc>) -> DateTime<Utc>,
+    ) -> Result<R, Error> {
+        let (result, entry) = entry(response, collector, convert, expires)?;
+        if let Some(entry) = entry {
+            let _ = self.write(operation, home_id, &entry);
+        }
+        Ok(result)
+    }
+
+    /// Write `entry` atomically, so readers never see half a file
+    pub fn write(&self, operation: &str, home_id: &HomeId, entry: &Entry) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(operation, home_id);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(entry)?)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// Result from a cached response body, None if it can't be used
+pub(crate) fn decode<D: DeserializeOwned, R>(
+    response: &str,
+    convert: impl FnOnce(D) -> Result<R, Error>,
+) -> Option<R> {
+    let response = serde_json::from_str(response).ok()?;
+    convert(response_data(response, None).ok()?).ok()
+}
+
+/// Convert a fresh response, and make an entry to cache it if it has no
+/// errors
+pub(crate) fn entry<D: DeserializeOwned, R>(
+    response: &str,
+    collector: Option<&Mutex<Vec<GraphQLError>>>,
+    convert: impl FnOnce(D) -> Result<R, Error>,
+    expires: impl FnOnce(&R, DateTime<Utc>) -> DateTime<Utc>,
+) -> Result<(R, Option<Entry>), Error> {
+    let now = Utc::now();
+    let parsed: graphql_client::Response<D> = serde_json::from_str(response)?;
+    let clean = parsed.errors.as_ref().is_none_or(Vec::is_empty);
+    let result = convert(response_data(parsed, collector)?)?;
+    let entry = clean.then(|| Entry {
+        expires: expires(&result, now).to_rfc3339(),
+        response: response.to_owned(),
+    });
+    Ok((result, entry))
+}
+
+/// 64 bit FNV-1a hash of `parts`, which unlike the std hashers is the same
+/// across builds, so cache files stay valid after an upgrade
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Length of the intervals in a list of prices, hourly if it can't be told
+fn interval(prices: &[PriceInfo]) -> Duration {
+    match prices {
+        [.., a, b] => b.starts_at - a.starts_at,
+        _ => Duration::hours(1),
+    }
+}
+
+/// Todays prices are fixed until the day is over
+pub(crate) fn today_expiry(prices: &[PriceInfo], now: DateTime<Utc>) -> DateTime<Utc> {
+    match prices.last() {
+        Some(last) => (last.starts_at + interval(prices)).with_timezone(&Utc),
+        None => now + RETRY,
+    }
+}
+
+/// Tomorrows prices are fixed until they become todays prices. Before they
+/// are published, ask again at publication time.
+pub(crate) fn tomorrow_expiry(prices: &[PriceInfo], now: DateTime<Utc>) -> DateTime<Utc> {
+    match prices.first() {
+        Some(first) => first.starts_at.with_timezone(&Utc),
+        None => {
+            let publish = now
+                .date_naive()
+                .and_hms_opt(PUBLISH_HOUR_UTC, 0, 0)
+                .map(|t| t.and_utc());
+            match publish {
+                Some(publish) if publish > now => publish,
+                _ => now + RETRY,
+            }
+        }
+    }
+}
+
+/// The current price holds until the next interval boundary. Intervals
+/// may be as short as 15 minutes, so that is the boundary used.
+pub(crate) fn current_expiry(price: &PriceInfo, now: DateTime<Utc>) -> DateTime<Utc> {
+    let quarter = Duration::minutes(15);
+    let start = price.starts_at.with_timezone(&Utc);
+    let passed = (now - start).num_seconds().max(0) / quarter.num_seconds();
+    start + quarter * (passed as i32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn prices(first: &str, step: Duration, n: i32) -> Vec<PriceInfo> {
+        let first = DateTime::parse_from_rfc3339(first).unwrap();
+        (0..n)
+            .map(|i| PriceInfo {
+                total: 1.0,
+                energy: 0.8,
+                tax: 0.2,
+                starts_at: first + step * i,
+                currency: "NOK".into(),
+                level: PriceLevel::Normal,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn today_expires_at_end_of_last_interval() {
+        let now = utc("2024-03-01T12:00:00Z");
+        let hourly = prices("2024-03-01T00:00:00+01:00", Duration::hours(1), 24);
+        assert_eq!(today_expiry(&hourly, now), utc("2024-03-01T23:00:00Z"));
+        let quarters = prices("2024-03-01T00:00:00+01:00", Duration::minutes(15), 96);
+        assert_eq!(today_expiry(&quarters, now), utc("2024-03-01T23:00:00Z"));
+        assert_eq!(today_expiry(&[], now), now + RETRY);
+    }
+
+    #[test]
+    fn tomorrow_expires_when_it_becomes_today() {
+        let now = utc("2024-03-01T14:00:00Z");
+        let tomorrow = prices("2024-03-02T00:00:00+01:00", Duration::hours(1), 24);
+        assert_eq!(tomorrow_expiry(&tomorrow, now), utc("2024-03-01T23:00:00Z"));
+    }
+
+    #[test]
+    fn missing_tomorrow_is_retried_from_publication() {
+        let morning = utc("2024-03-01T08:00:00Z");
+        assert_eq!(tomorrow_expiry(&[], morning), utc("2024-03-01T11:00:00Z"));
+        let afternoon = utc("2024-03-01T12:30:00Z");
+        assert_eq!(tomorrow_expiry(&[], afternoon), afternoon + RETRY);
+    }
+
+    #[test]
+    fn current_expires_at_next_quarter() {
+        let price = &prices("2024-03-01T13:00:00+01:00", Duration::hours(1), 1)[0];
+        let expiry = |now| current_expiry(price, utc(now));
+        assert_eq!(expiry("2024-03-01T12:00:00Z"), utc("2024-03-01T12:15:00Z"));
+        assert_eq!(expiry("2024-03-01T12:29:59Z"), utc("2024-03-01T12:30:00Z"));
+        assert_eq!(expiry("2024-03-01T12:45:00Z"), utc("2024-03-01T13:00:00Z"));
+        // A clock behind the interval start waits for the first quarter
+        assert_eq!(expiry("2024-03-01T11:59:00Z"), utc("2024-03-01T12:15:00Z"));
+    }
+}
@@ -0,0 +1,515 @@
+//! Norwegian capacity tariff (effekttrinn).
+//!
+//! The grid fee for a month is set by a step, chosen from the average of the
+//! three highest hourly peaks of the month, where each peak is on a different
+//! day. The steps and their prices vary between grid companies, so they are
+//! given as a [`StepTable`].
+//!
+//! Peaks are found in hourly [`Consumption`], using the time zone of the data
+//! to tell days and months apart. [`PeakGuard`] keeps an eye on the current
+//! hour using real time measurements.
+use crate::*;
+use chrono::{Datelike, Duration, DurationRound, NaiveDate};
+
+/// Number of daily peaks that make up the monthly average
+const PEAKS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The highest hour of a day
+pub struct Peak {
+    /// The day
+    pub day: NaiveDate,
+    /// Start of the hour
+    pub from: DateTime<FixedOffset>,
+    /// Average power during the hour (kW)
+    pub kw: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+/// A capacity step, as read from a [`tariff`](crate::tariff) file
+pub struct Step {
+    /// Lowest average peak (kW) in this step
+    pub from_kw: f64,
+    /// Price pr month
+    pub monthly_price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The steps of a grid company, e.g. 0-2 kW, 2-5 kW, 5-10 kW and so on
+pub struct StepTable {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Capacity step of a month, and how close it is to the next step
+pub struct CapacityStatus {
+    /// The daily peaks counted, highest first
+    pub peaks: Vec<Peak>,
+    /// Average of the peaks (kW)
+    pub average_kw: f64,
+    /// Index of the current step in the table
+    pub step: usize,
+    /// Price pr month of the current step
+    pub monthly_price: f64,
+    /// Where the next step starts (kW), None in the highest step
+    pub next_step_kw: Option<f64>,
+    /// How much the average can grow before reaching the next step (kW)
+    pub headroom_kw: Option<f64>,
+    /// A single hour above this (kW), on a day that is not among the two
+    /// highest, moves the month to the next step
+    pub trigger_kw: Option<f64>,
+}
+
+impl StepTable {
+    /// Create a table from its steps, in any order
+    pub fn new(mut steps: Vec<Step>) -> Self {
+        steps.sort_by(|a, b| a.from_kw.total_cmp(&b.from_kw));
+        StepTable { steps }
+    }
+
+    /// The steps, lowest first
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Index of the step `kw` falls in, None if below the first step
+    pub fn step(&self, kw: f64) -> Option<usize> {
+        self.steps.iter().rposition(|s| s.from_kw <= kw)
+    }
+
+    /// Capacity step for the month `year`-`month` given so far by
+    /// `consumption`. Early in the month there may be fewer than three days
+    /// to average over.
+    pub fn status(
+        &self,
+        consumption: &[Consumption],
+        year: i32,
+        month: u32,
+    ) -> Option<CapacityStatus> {
+        let peaks = top_peaks(consumption, year, month);
+        let average_kw = average(&peaks);
+        let step = self.step(average_kw)?;
+        let next_step_kw = self.steps.get(step + 1).map(|s| s.from_kw);
+        let trigger_kw = next_step_kw.map(|next| {
+            let kept = &peaks[..peaks.len().min(PEAKS - 1)];
+            next * (kept.len() + 1) as f64 - kept.iter().map(|p| p.kw).sum::<f64>()
+        });
+        Some(CapacityStatus {
+            average_kw,
+            step,
+            monthly_price: self.steps[step].monthly_price,
+            next_step_kw,
+            headroom_kw: next_step_kw.map(|next| next - average_kw),
+            trigger_kw,
+            peaks,
+        })
+    }
+}
+
+/// Average power (kW) in an interval of consumption, None without data
+pub(crate) fn power(c: &Consumption) -> Option<f64> {
+    let hours = (c.to - c.from).num_seconds() as f64 / 3600.0;
+    match c.energy {
+        EnergyUnits::kWh(e) if hours > 0.0 => Some(e / hours),
+        _ => None,
+    }
+}
+
+/// The highest hour of each day in `consumption`, highest first
+pub fn daily_peaks(consumption: &[Consumption]) -> Vec<Peak> {
+    let mut peaks: Vec<Peak> = Vec::new();
+    for c in consumption {
+        let kw = match power(c) {
+            Some(kw) => kw,
+            None => continue,
+        };
+        add_peak(&mut peaks, c.from, kw);
+    }
+    peaks.sort_by(|a, b| b.kw.total_cmp(&a.kw));
+    peaks
+}
+
+/// Count an hour starting at `from` towards the peak of its day
+fn add_peak(peaks: &mut Vec<Peak>, from: DateTime<FixedOffset>, kw: f64) {
+    let day = from.date_naive();
+    match peaks.iter_mut().find(|p| p.day == day) {
+        Some(p) if p.kw < kw => {
+            p.from = from;
+            p.kw = kw;
+        }
+        Some(_) => {}
+        None => peaks.push(Peak { day, from, kw }),
+    }
+}
+
+/// The three highest daily peaks in the month `year`-`month`, highest first
+pub fn top_peaks(consumption: &[Consumption], year: i32, month: u32) -> Vec<Peak> {
+    let mut peaks = daily_peaks(consumption);
+    peaks.retain(|p| p.day.year() == year && p.day.month() == month);
+    peaks.truncate(PEAKS);
+    peaks
+}
+
+/// Average of the peaks (kW), zero without any
+pub fn average(peaks: &[Peak]) -> f64 {
+    if peaks.is_empty() {
+        return 0.0;
+    }
+    peaks.iter().map(|p| p.kw).sum::<f64>() / peaks.len() as f64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Warning from [`PeakGuard::update()`] that the hour will end above the limit
+pub struct PeakWarning {
+    /// Start of the hour
+    pub hour: DateTime<FixedOffset>,
+    /// Expected consumption for the whole hour at the current power (kWh)
+    pub projected_kwh: f64,
+    /// Consumption the hour should stay below (kWh)
+    pub limit_kwh: f64,
+    /// Power to cut for the rest of the hour to stay below the limit (kW)
+    pub shed_kw: f64,
+}
+
+#[derive(Debug, Clone)]
+enum Limit {
+    Fixed(f64),
+    Steps(StepTable),
+}
+
+/// Watches real time measurements, and warns when the current hour is on
+/// its way past a limit.
+///
+/// The consumption for the hour is projected from
+/// [`LiveMeasurement::accumulated_consumption_last_hour`], assuming the
+/// current power holds for the rest of the hour. The limit is either fixed,
+/// or the consumption that would move the month to the next capacity step.
+#[derive(Debug, Clone)]
+pub struct PeakGuard {
+    limit: Limit,
+    peaks: Vec<Peak>,
+    hour: Option<DateTime<FixedOffset>>,
+    hour_kwh: f64,
+    /// Consumption the hour was projected to end at, by the last measurement
+    hour_projected_kwh: f64,
+}
+
+impl PeakGuard {
+    /// Guard against reaching the next step in `table`. The peaks so far this
+    /// month are taken from hourly `history`, e.g. from
+    /// [`TibberSession::get_consumption()`].
+    pub fn new(table: StepTable, history: &[Consumption]) -> Self {
+        PeakGuard {
+            limit: Limit::Steps(table),
+            peaks: daily_peaks(history),
+            hour: None,
+            hour_kwh: 0.0,
+            hour_projected_kwh: 0.0,
+        }
+    }
+
+    /// Guard against any hour using more than `kwh`
+    pub fn with_limit(kwh: f64) -> Self {
+        PeakGuard {
+            limit: Limit::Fixed(kwh),
+            peaks: Vec::new(),
+            hour: None,
+            hour_kwh: 0.0,
+            hour_projected_kwh: 0.0,
+        }
+    }
+
+    /// Daily peaks seen so far, including completed live hours
+    pub fn peaks(&self) -> &[Peak] {
+        &self.peaks
+    }
+
+    /// Consumption (kWh) the hour starting at `hour` should stay below, None
+    /// when already in the highest step
+    pub fn limit(&self, hour: DateTime<FixedOffset>) -> Option<f64> {
+        let table = match &self.limit {
+            Limit::Fixed(kwh) => return Some(*kwh),
+            Limit::Steps(table) => table,
+        };
+        let day = hour.date_naive();
+        let mut month: Vec<Peak> = self
+            .peaks
+            .iter()
+            .filter(|p| p.day.year() == day.year() && p.day.month() == day.month())
+            .cloned()
+            .collect();
+        if self.hour == Some(hour) {
+            add_peak(&mut month, hour, self.hour_kwh);
+        }
+        month.sort_by(|a, b| b.kw.total_cmp(&a.kw));
+        month.truncate(PEAKS);
+        let next = table.steps.get(table.step(average(&month))? + 1)?.from_kw;
+
+        // The hour counts through the peak of its day, so compare against
+        // the highest other days
+        let others: Vec<f64> = month
+            .iter()
+            .filter(|p| p.day != day)
+            .map(|p| p.kw)
+            .take(PEAKS - 1)
+            .collect();
+        Some(next * (others.len() + 1) as f64 - others.iter().sum::<f64>())
+    }
+
+    /// Add a measurement, and get a warning if the current hour is projected
+    /// to end above the limit.
+    ///
+    /// When a new hour starts, the hour that ended counts towards the peaks
+    /// with the consumption it was projected to end at by its last
+    /// measurement, as the measurements stop short of the end of the hour.
+    pub fn update(&mut self, measurement: &LiveMeasurement) -> Option<PeakWarning> {
+        let time = measurement.timestamp;
+        let hour = time.duration_trunc(Duration::hours(1)).ok()?;
+        match self.hour {
+            Some(h) if h == hour => {}
+            Some(h) => {
+                add_peak(&mut self.peaks, h, self.hour_projected_kwh);
+                self.hour = Some(hour);
+            }
+            None => self.hour = Some(hour),
+        }
+        self.hour_kwh = measurement.accumulated_consumption_last_hour;
+
+        let remaining = (hour + Duration::hours(1) - time).num_seconds() as f64 / 3600.0;
+        let projected_kwh = self.hour_kwh + measurement.power / 1000.0 * remaining;
+        self.hour_projected_kwh = projected_kwh;
+        let limit_kwh = self.limit(hour)?;
+        if projected_kwh <= limit_kwh {
+            return None;
+        }
+        let shed_kw = match remaining > 0.0 {
+            true => (projected_kwh - limit_kwh) / remaining,
+            false => 0.0,
+        };
+        Some(PeakWarning {
+            hour,
+            projected_kwh,
+            limit_kwh,
+            shed_kw,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hour(from: &str, kwh: f64) -> Consumption {
+        let from = DateTime::parse_from_rfc3339(from).unwrap();
+        Consumption {
+            from,
+            to: from + Duration::hours(1),
+            cost: Some(kwh),
+            total_cost: None,
+            unit_cost: None,
+            unit_price: Some(1.0),
+            unit_price_vat: None,
+            currency: Some("NOK".into()),
+            energy: EnergyUnits::kWh(kwh),
+        }
+    }
+
+    fn table() -> StepTable {
+        StepTable::new(vec![
+            Step {
+                from_kw: 5.0,
+                monthly_price: 300.0,
+            },
+            Step {
+                from_kw: 0.0,
+                monthly_price: 100.0,
+            },
+            Step {
+                from_kw: 2.0,
+                monthly_price: 200.0,
+            },
+        ])
+    }
+
+    #[test]
+    fn a_day_counts_once() {
+        let consumption = [
+            hour("2024-03-04T08:00:00+01:00", 5.0),
+            hour("2024-03-04T18:00:00+01:00", 4.0),
+            hour("2024-03-05T18:00:00+01:00", 3.0),
+        ];
+        let peaks = top_peaks(&consumption, 2024, 3);
+        let kw: Vec<f64> = peaks.iter().map(|p| p.kw).collect();
+        assert_eq!(kw, [5.0, 3.0]);
+        assert_eq!(peaks[0].from, consumption[0].from);
+    }
+
+    #[test]
+    fn other_months_are_left_out() {
+        // The first hour is February in local time, though March in UTC
+        let consumption = [
+            hour("2024-02-29T23:00:00-01:00", 9.0),
+            hour("2024-03-01T00:00:00+01:00", 1.0),
+            hour("2024-04-01T00:00:00+02:00", 8.0),
+        ];
+        let peaks = top_peaks(&consumption, 2024, 3);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].kw, 1.0);
+    }
+
+    #[test]
+    fn early_in_the_month_fewer_days_are_averaged() {
+        let consumption = [
+            hour("2024-03-01T08:00:00+01:00", 4.0),
+            hour("2024-03-02T08:00:00+01:00", 1.0),
+        ];
+        let status = table().status(&consumption, 2024, 3).unwrap();
+        assert_eq!(status.peaks.len(), 2);
+        assert_eq!(status.average_kw, 2.5);
+        assert_eq!(status.step, 1);
+        assert_eq!(status.monthly_price, 200.0);
+    }
+
+    #[test]
+    fn headroom_and_trigger() {
+        let consumption = [
+            hour("2024-03-01T08:00:00+01:00", 4.0),
+            hour("2024-03-02T08:00:00+01:00", 3.0),
+            hour("2024-03-03T08:00:00+01:00", 2.0),
+            hour("2024-03-04T08:00:00+01:00", 1.0),
+        ];
+        let status = table().status(&consumption, 2024, 3).unwrap();
+        assert_eq!(status.average_kw, 3.0);
+        assert_eq!(status.next_step_kw, Some(5.0));
+        assert_eq!(status.headroom_kw, Some(2.0));
+        // With 4 and 3 kept, an hour of 8 kW brings the average to 5
+        assert_eq!(status.trigger_kw, Some(8.0));
+
+        let top = [hour("2024-03-01T08:00:00+01:00", 6.0)];
+        let status = table().status(&top, 2024, 3).unwrap();
+        assert_eq!(status.step, 2);
+        assert_eq!(status.next_step_kw, None);
+        assert_eq!(status.headroom_kw, None);
+        assert_eq!(status.trigger_kw, None);
+    }
+
+    #[test]
+    fn below_the_first_step() {
+        let table = StepTable::new(vec![Step {
+            from_kw: 1.0,
+            monthly_price: 100.0,
+        }]);
+        assert_eq!(table.step(0.5), None);
+        assert_eq!(table.step(1.0), Some(0));
+        let consumption = [hour("2024-03-01T08:00:00+01:00", 0.5)];
+        assert_eq!(table.status(&consumption, 2024, 3), None);
+    }
+
+    fn measurement(timestamp: &str, last_hour_kwh: f64, watt: f64) -> LiveMeasurement {
+        LiveMeasurement {
+            timestamp: DateTime::parse_from_rfc3339(timestamp).unwrap(),
+            power: watt,
+            last_meter_consumption: None,
+            accumulated_consumption: last_hour_kwh,
+            accumulated_production: 0.0,
+            accumulated_consumption_last_hour: last_hour_kwh,
+            accumulated_production_last_hour: 0.0,
+            accumulated_cost: None,
+            accumulated_reward: None,
+            currency: None,
+            min_power: watt,
+            average_power: watt,
+            max_power: watt,
+            power_production: None,
+            power_reactive: None,
+            power_production_reactive: None,
+            min_power_production: None,
+            max_power_production: None,
+            last_meter_production: None,
+            power_factor: None,
+            voltage: [None; 3],
+            current: [None; 3],
+            signal_strength: None,
+        }
+    }
+
+    #[test]
+    fn guard_with_fixed_limit() {
+        let mut guard = PeakGuard::with_limit(5.0);
+        // Half the hour left: 2 kWh so far, and 2, 3 or 4 kWh to come
+        assert_eq!(
+            guard.update(&measurement("2024-03-04T10:30:00+01:00", 2.0, 4000.0)),
+            None
+        );
+        assert_eq!(
+            guard.update(&measurement("2024-03-04T10:30:00+01:00", 2.0, 6000.0)),
+            None
+        );
+        let warning = guard
+            .update(&measurement("2024-03-04T10:30:00+01:00", 2.0, 8000.0))
+            .unwrap();
+        assert_eq!(
+            warning.hour,
+            DateTime::parse_from_rfc3339("2024-03-04T10:00:00+01:00").unwrap()
+        );
+        assert_eq!(warning.projected_kwh, 6.0);
+        assert_eq!(warning.limit_kwh, 5.0);
+        assert_eq!(warning.shed_kw, 2.0);
+    }
+
+    #[test]
+    fn guard_with_step_limit() {
+        let history = [
+            hour("2024-03-01T08:00:00+01:00", 4.0),
+            hour("2024-03-02T08:00:00+01:00", 3.0),
+        ];
+        let mut guard = PeakGuard::new(table(), &history);
+        // 4 and 3 are kept, so 8 kWh brings the average to the 5 kW step
+        let now = "2024-03-03T10:30:00+01:00";
+        assert_eq!(
+            guard.limit(DateTime::parse_from_rfc3339("2024-03-03T10:00:00+01:00").unwrap()),
+            Some(8.0)
+        );
+        assert_eq!(guard.update(&measurement(now, 3.0, 10000.0)), None);
+        let warning = guard.update(&measurement(now, 3.0, 12000.0)).unwrap();
+        assert_eq!(warning.limit_kwh, 8.0);
+        assert_eq!(warning.projected_kwh, 9.0);
+        assert_eq!(warning.shed_kw, 2.0);
+    }
+
+    #[test]
+    fn finished_hour_counts_as_projected() {
+        let mut guard = PeakGuard::with_limit(10.0);
+        // Ten minutes left at 6 kW, so the hour ends at 3 kWh
+        guard.update(&measurement("2024-03-04T10:50:00+01:00", 2.0, 6000.0));
+        assert!(guard.peaks().is_empty());
+        guard.update(&measurement("2024-03-04T11:05:00+01:00", 0.1, 1000.0));
+        let peaks = guard.peaks();
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(
+            peaks[0].from,
+            DateTime::parse_from_rfc3339("2024-03-04T10:00:00+01:00").unwrap()
+        );
+        assert!((peaks[0].kw - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn guard_starts_over_in_a_new_month() {
+        let history = [
+            hour("2024-02-27T08:00:00+01:00", 9.0),
+            hour("2024-02-28T08:00:00+01:00", 9.0),
+        ];
+        let mut guard = PeakGuard::new(table(), &history);
+        guard.update(&measurement("2024-02-29T23:50:00+01:00", 8.0, 6000.0));
+        guard.update(&measurement("2024-03-01T00:05:00+01:00", 0.1, 1000.0));
+        assert_eq!(guard.peaks().len(), 3);
+        // February is in the highest step, but March starts in the lowest
+        // with nothing to average against
+        let midnight = DateTime::parse_from_rfc3339("2024-03-01T00:00:00+01:00").unwrap();
+        assert_eq!(guard.limit(midnight), Some(2.0));
+    }
+}
@@ -0,0 +1,210 @@
+//! Error type returned by all API calls
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single entry from the `errors` list of a GraphQL response
+pub struct GraphQLError {
+    /// Human readable description of the error
+    pub message: String,
+    /// Line and column in the query the error applies to
+    pub locations: Vec<(i32, i32)>,
+    /// Path to the field that failed, e.g. `["viewer", "home"]`
+    pub path: Vec<String>,
+    /// Machine readable error code from the extensions, e.g. `UNAUTHENTICATED`
+    pub code: Option<String>,
+}
+
+impl GraphQLError {
+    pub(crate) fn new(e: graphql_client::Error) -> Self {
+        GraphQLError {
+            locations: e
+                .locations
+                .unwrap_or_default()
+                .into_iter()
+                .map(|l| (l.line, l.column))
+                .collect(),
+            path: e
+                .path
+                .unwrap_or_default()
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            code: e
+                .extensions
+                .and_then(|mut ext| ext.remove("code"))
+                .and_then(|c| c.as_str().map(str::to_owned)),
+            message: e.message,
+        }
+    }
+}
+
+impl fmt::Display for GraphQLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(code) = &self.code {
+            write!(f, " [{}]", code)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Data returned together with GraphQL errors for the fields that failed
+pub struct Partial<T> {
+    /// The data that could be resolved
+    pub data: T,
+    /// Errors for the parts of the query that could not be resolved
+    pub errors: Vec<GraphQLError>,
+}
+
+#[derive(Debug)]
+/// Errors that can occur when talking to the Tibber API
+#[non_exhaustive]
+pub enum Error {
+    /// Request could not be delivered, or the connection failed mid-request
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// The API answered with a non-success HTTP status code
+    Http(u16),
+    /// The API returned one or more GraphQL error entries
+    GraphQL(Vec<GraphQLError>),
+    /// Response was not valid JSON, or did not match the query
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+    /// A field needed to build the result was missing in the response
+    MissingField(&'static str),
+    /// A timestamp in the response could not be parsed
+    Timestamp {
+        /// The offending text
+        value: String,
+        /// Reason given by the parser
+        source: chrono::ParseError,
+    },
+    /// Unexpected message from the server
+    Protocol(String),
+    /// The requested functionality is not available for this home
+    Unsupported(&'static str),
+    /// Input was rejected before sending it to the API
+    InvalidInput(&'static str),
+    /// Reading or writing local storage failed
+    Storage(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    /// The failure is likely to go away if the request is retried later
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Transport(_) => true,
+            Error::Http(status) => matches!(status, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+
+    /// The API token was rejected, or lacks access to the requested data
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Error::Http(status) => matches!(status, 401 | 403),
+            Error::GraphQL(errors) => errors
+                .iter()
+                .any(|e| matches!(e.code.as_deref(), Some("UNAUTHENTICATED" | "FORBIDDEN"))),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn timestamp(value: &str, source: chrono::ParseError) -> Self {
+        Error::Timestamp {
+            value: value.to_owned(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Http(status) => write!(f, "HTTP status {}", status),
+            Error::GraphQL(errors) => {
+                write!(f, "GraphQL error: ")?;
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            Error::Decode(e) => write!(f, "could not decode response: {}", e),
+            Error::MissingField(field) => write!(f, "missing field in response: {}", field),
+            Error::Timestamp { value, source } => {
+                write!(f, "could not parse timestamp {:?}: {}", value, source)
+            }
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Unsupported(what) => write!(f, "not supported: {}", what),
+            Error::InvalidInput(why) => write!(f, "invalid input: {}", why),
+            Error::Storage(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) | Error::Decode(e) | Error::Storage(e) => Some(e.as_ref()),
+            Error::Timestamp { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "reqwest", feature = "async"))]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        match e.status() {
+            Some(status) if !status.is_success() => Error::Http(status.as_u16()),
+            _ if e.is_decode() => Error::Decode(Box::new(e)),
+            _ => Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl From<ureq_crate::Error> for Error {
+    fn from(e: ureq_crate::Error) -> Self {
+        match e {
+            ureq_crate::Error::Status(status, _) => Error::Http(status),
+            e => Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(feature = "live")]
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Http(response) => Error::Http(response.status().as_u16()),
+            e => Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(feature = "store")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Storage(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(Box::new(e))
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Decode(Box::new(e))
+    }
+}
@@ -4,12 +4,51 @@
 //! Simple bindings to Tibber GraphQL API
 //!
 //! Docs of underlying API : https://developer.tibber.com/docs/overview
-#[cfg(feature = "reqwest")]
-use ::reqwest::blocking::Client;
+//!
+//! With the `serde` feature the model types, such as [`User`], [`House`],
+//! [`PriceInfo`] and [`Consumption`], implement `Serialize` and `Deserialize`.
+//! Fields keep their Rust names, enum variants are externally tagged with
+//! their Rust names, and timestamps are RFC 3339 strings. The same goes for
+//! results calculated by the crate, such as [`capacity_tariff::CapacityStatus`].
+//!
+//! Configuration that is read from files, the [`tariff`] definitions and the
+//! [`capacity_tariff::Step`] they contain, always implements `Serialize` and
+//! `Deserialize`. With the `toml` feature, [`tariff::Tariffs`] can also be read
+//! from TOML.
 use chrono::{DateTime, FixedOffset};
-#[cfg(feature = "reqwest")]
-use graphql_client::reqwest::post_graphql_blocking as post_graphql;
 use graphql_client::GraphQLQuery;
+use std::sync::{Arc, Mutex};
+
+mod builder;
+mod cache;
+pub mod capacity_tariff;
+mod error;
+mod paging;
+pub mod planner;
+pub mod tariff;
+mod transport;
+pub use builder::TibberSessionBuilder;
+pub use error::{Error, GraphQLError, Partial};
+#[cfg(feature = "reqwest")]
+pub use transport::ReqwestTransport;
+#[cfg(feature = "ureq")]
+pub use transport::UreqTransport;
+#[cfg(feature = "async")]
+pub use transport::{AsyncReqwestTransport, AsyncTransport};
+pub use transport::{DefaultTransport, FixtureTransport, Transport};
+
+#[cfg(not(any(feature = "reqwest", feature = "ureq")))]
+compile_error!("Either the `reqwest` or the `ureq` feature must be enabled");
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::AsyncTibberSession;
+#[cfg(feature = "live")]
+mod live;
+#[cfg(feature = "live")]
+pub use live::LiveStream;
+#[cfg(feature = "store")]
+pub mod store;
 
 // The paths are relative to the directory where your `Cargo.toml` is located.
 // Both json and the GraphQL schema language are supported as sources for the schema
@@ -69,69 +108,140 @@ struct ConsumptionHistory;
 )]
 struct ProductionHistory;
 
-#[cfg(feature = "reqwest")]
-fn make_request<Q: GraphQLQuery>(
-    api_token: &str,
-    variables: <Q as GraphQLQuery>::Variables,
-) -> Result<graphql_client::Response<Q::ResponseData>, Box<dyn std::error::Error>> {
-    let client = Client::builder()
-        .user_agent("graphql-rust/0.14.0")
-        .default_headers(
-            std::iter::once((
-                reqwest::header::AUTHORIZATION,
-                reqwest::header::HeaderValue::from_str(&format!("Bearer {}", api_token))?,
-            ))
-            .collect(),
-        )
-        .build()?;
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/send_meter_reading.graphql",
+    response_derives = "Debug"
+)]
+struct SendMeterReading;
 
-    Ok(post_graphql::<Q, _>(
-        &client,
-        "https://api.tibber.com/v1-beta/gql/",
-        variables,
-    )?)
-}
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/update_home.graphql",
+    response_derives = "Debug",
+    skip_serializing_none
+)]
+struct UpdateHome;
 
-#[cfg(feature = "ureq")]
-fn make_request<Q: GraphQLQuery>(
-    api_token: &str,
-    variables: <Q as GraphQLQuery>::Variables,
-) -> Result<graphql_client::Response<Q::ResponseData>, Box<dyn std::error::Error>> {
-    let agent = ureq_crate::AgentBuilder::new()
-        .user_agent("graphql-rust/0.14.0")
-        .build();
-
-    let body = Q::build_query(variables);
-    Ok(agent
-        .post("https://api.tibber.com/v1-beta/gql/")
-        .set("Authorization", &format!("Bearer {}", api_token))
-        .send_json(&body)?
-        .into_json()?)
-}
-
-fn fetch_data<T: GraphQLQuery>(
-    api_token: &str,
-    variables: <T as GraphQLQuery>::Variables,
-) -> Result<<T as GraphQLQuery>::ResponseData, Box<dyn std::error::Error>> {
-    let response_body = make_request::<T>(api_token, variables)?;
-
-    let response_data = match response_body.data {
-        Some(d) => d,
-        None => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "no response body",
-            )))
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/send_push_notification.graphql",
+    response_derives = "Debug",
+    skip_serializing_none
+)]
+struct SendPushNotification;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/subscriptions.graphql",
+    response_derives = "Debug"
+)]
+struct Subscriptions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/price_range.graphql",
+    response_derives = "Debug"
+)]
+struct PriceRange;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/price_rating.graphql",
+    response_derives = "Debug"
+)]
+struct PriceRatings;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/consumption_range.graphql",
+    response_derives = "Debug"
+)]
+struct ConsumptionRange;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/production_range.graphql",
+    response_derives = "Debug"
+)]
+struct ProductionRange;
+
+/// Extract the data from a response. GraphQL errors fail the request, unless
+/// a collector is given and there is partial data to return.
+fn response_data<T>(
+    response_body: graphql_client::Response<T>,
+    collector: Option<&Mutex<Vec<GraphQLError>>>,
+) -> Result<T, Error> {
+    let errors: Vec<GraphQLError> = response_body
+        .errors
+        .unwrap_or_default()
+        .into_iter()
+        .map(GraphQLError::new)
+        .collect();
+    match (response_body.data, collector) {
+        (Some(data), _) if errors.is_empty() => Ok(data),
+        (Some(data), Some(collector)) => {
+            collector
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .extend(errors);
+            Ok(data)
         }
-    };
-    Ok(response_data)
+        _ if !errors.is_empty() => Err(Error::GraphQL(errors)),
+        _ => Err(Error::MissingField("data")),
+    }
+}
+
+/// Check the HTTP status of a response. A failed request is reported by the
+/// GraphQL errors in the body if there are any, and by the status otherwise.
+fn check_status(status: u16, body: &str) -> Result<(), Error> {
+    if (200..300).contains(&status) {
+        return Ok(());
+    }
+    let errors = serde_json::from_str::<graphql_client::Response<serde_json::Value>>(body)
+        .ok()
+        .and_then(|response| response.errors)
+        .unwrap_or_default();
+    match errors.is_empty() {
+        true => Err(Error::Http(status)),
+        false => Err(Error::GraphQL(
+            errors.into_iter().map(GraphQLError::new).collect(),
+        )),
+    }
+}
+
+/// Combine the result of a partial data call with the collected errors. A
+/// field missing due to a failed part of the query is reported as those errors.
+fn partial_result<T>(
+    data: Result<T, Error>,
+    errors: Vec<GraphQLError>,
+) -> Result<Partial<T>, Error> {
+    match data {
+        Ok(data) => Ok(Partial { data, errors }),
+        Err(Error::MissingField(_)) if !errors.is_empty() => Err(Error::GraphQL(errors)),
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<FixedOffset>, Error> {
+    DateTime::parse_from_rfc3339(time).map_err(|e| Error::timestamp(time, e))
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// ID used to represent a house / home
 pub struct HomeId(String);
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// User info for current authenticated user
 pub struct User {
     /// Login name of the user
@@ -147,6 +257,7 @@ pub struct User {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Type / building-category of house
 pub enum HouseType {
     /// An apartment in a block
@@ -162,18 +273,56 @@ pub enum HouseType {
 }
 
 impl HouseType {
-    fn new(htype: home::HomeType) -> Self {
-        match htype {
-            home::HomeType::APARTMENT => HouseType::Apartment,
-            home::HomeType::ROWHOUSE => HouseType::RowHouse,
-            home::HomeType::HOUSE => HouseType::House,
-            home::HomeType::COTTAGE => HouseType::Cottage,
-            home::HomeType::Other(s) => HouseType::Other(s),
+    fn input(self) -> update_home::HomeType {
+        match self {
+            HouseType::Apartment => update_home::HomeType::APARTMENT,
+            HouseType::RowHouse => update_home::HomeType::ROWHOUSE,
+            HouseType::House => update_home::HomeType::HOUSE,
+            HouseType::Cottage => update_home::HomeType::COTTAGE,
+            HouseType::Other(s) => update_home::HomeType::Other(s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Avatar shown for the home in the App
+pub enum HomeAvatar {
+    /// Apartment
+    Apartment,
+    /// Row house
+    RowHouse,
+    /// House with one floor
+    FloorHouse1,
+    /// House with two floors
+    FloorHouse2,
+    /// House with three floors
+    FloorHouse3,
+    /// Cottage
+    Cottage,
+    /// Castle
+    Castle,
+    /// Something else
+    Other(String),
+}
+
+impl HomeAvatar {
+    fn input(self) -> update_home::HomeAvatar {
+        match self {
+            HomeAvatar::Apartment => update_home::HomeAvatar::APARTMENT,
+            HomeAvatar::RowHouse => update_home::HomeAvatar::ROWHOUSE,
+            HomeAvatar::FloorHouse1 => update_home::HomeAvatar::FLOORHOUSE1,
+            HomeAvatar::FloorHouse2 => update_home::HomeAvatar::FLOORHOUSE2,
+            HomeAvatar::FloorHouse3 => update_home::HomeAvatar::FLOORHOUSE3,
+            HomeAvatar::Cottage => update_home::HomeAvatar::COTTAGE,
+            HomeAvatar::Castle => update_home::HomeAvatar::CASTLE,
+            HomeAvatar::Other(s) => update_home::HomeAvatar::Other(s),
         }
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Primary source of heating
 pub enum HeatingSource {
     /// AC unit with inverter
@@ -195,24 +344,27 @@ pub enum HeatingSource {
 }
 
 impl HeatingSource {
-    fn new(hsource: Option<home::HeatingSource>) -> Self {
-        if hsource.is_none() {
-            return HeatingSource::Unknown;
-        }
-        match hsource.unwrap() {
-            home::HeatingSource::AIR2AIR_HEATPUMP => HeatingSource::Air2AairHeatPump,
-            home::HeatingSource::ELECTRICITY => HeatingSource::Electricity,
-            home::HeatingSource::GROUND => HeatingSource::Ground,
-            home::HeatingSource::DISTRICT_HEATING => HeatingSource::DistrictHeating,
-            home::HeatingSource::ELECTRIC_BOILER => HeatingSource::ElectricBoiler,
-            home::HeatingSource::AIR2WATER_HEATPUMP => HeatingSource::Air2WaterHeatPump,
-            home::HeatingSource::OTHER => HeatingSource::Other(None),
-            home::HeatingSource::Other(s) => HeatingSource::Other(Some(s)),
-        }
+    fn input(self) -> Result<update_home::HeatingSource, Error> {
+        Ok(match self {
+            HeatingSource::Air2AairHeatPump => update_home::HeatingSource::AIR2AIR_HEATPUMP,
+            HeatingSource::Electricity => update_home::HeatingSource::ELECTRICITY,
+            HeatingSource::Ground => update_home::HeatingSource::GROUND,
+            HeatingSource::DistrictHeating => update_home::HeatingSource::DISTRICT_HEATING,
+            HeatingSource::ElectricBoiler => update_home::HeatingSource::ELECTRIC_BOILER,
+            HeatingSource::Air2WaterHeatPump => update_home::HeatingSource::AIR2WATER_HEATPUMP,
+            HeatingSource::Other(None) => update_home::HeatingSource::OTHER,
+            HeatingSource::Other(Some(s)) => update_home::HeatingSource::Other(s),
+            HeatingSource::Unknown => {
+                return Err(Error::InvalidInput(
+                    "heating source can not be set to unknown",
+                ))
+            }
+        })
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Address of the home / house
 pub struct Address {
     /// Street adress
@@ -233,8 +385,11 @@ pub struct Address {
     pub longitude: Option<f64>,
 }
 
-impl Address {
-    fn new(addr: home::HomeViewerHomeAddress) -> Self {
+/// Build an [`Address`] from any of the generated address types, which all
+/// share the same fields
+macro_rules! address {
+    ($addr:expr) => {{
+        let addr = $addr;
         Address {
             address1: addr.address1,
             address2: addr.address2,
@@ -251,25 +406,188 @@ impl Address {
                 _ => None,
             },
         }
+    }};
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Whether a legal entity is a private person or a company
+pub enum OwnerKind {
+    /// A private person
+    Person {
+        /// First name
+        first_name: Option<String>,
+        /// Middle name
+        middle_name: Option<String>,
+        /// Last name
+        last_name: Option<String>,
+    },
+    /// A company or other organization
+    Company {
+        /// Organization number
+        organization_no: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Legal entity (person or company) owning a home / house, or subscribing
+/// to a power deal
+pub struct Owner {
+    /// Id of the legal entity
+    pub id: String,
+    /// Full name
+    pub name: String,
+    /// Person or company
+    pub kind: OwnerKind,
+    /// Preferred language
+    pub language: Option<String>,
+    /// E-mail address
+    pub email: Option<String>,
+    /// Mobile phone number
+    pub mobile: Option<String>,
+    /// Postal address
+    pub address: Option<Address>,
+}
+
+/// Build an [`Owner`] from any of the generated legal entity types
+macro_rules! owner {
+    ($owner:expr) => {{
+        let owner = $owner;
+        let kind = if owner.is_company == Some(true) {
+            OwnerKind::Company {
+                organization_no: owner.organization_no,
+            }
+        } else {
+            OwnerKind::Person {
+                first_name: owner.first_name,
+                middle_name: owner.middle_name,
+                last_name: owner.last_name,
+            }
+        };
+        let (email, mobile) = match owner.contact_info {
+            Some(c) => (c.email, c.mobile),
+            None => (None, None),
+        };
+        Owner {
+            id: owner.id,
+            name: owner.name,
+            kind,
+            language: owner.language,
+            email,
+            mobile,
+            address: owner.address.map(|a| address!(a)),
+        }
+    }};
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// State of a subscription / power deal
+pub enum SubscriptionStatus {
+    /// The subscription is active
+    Running,
+    /// Waiting to start, e.g. for the switch of supplier to go through
+    Awaiting(String),
+    /// The subscription has ended
+    Ended,
+    /// Something else
+    Other(String),
+    /// Not given
+    Unknown,
+}
+
+impl SubscriptionStatus {
+    fn new(status: Option<String>) -> Self {
+        match status {
+            Some(s) => match s.to_lowercase().as_str() {
+                "running" => SubscriptionStatus::Running,
+                "ended" => SubscriptionStatus::Ended,
+                a if a.starts_with("awaiting") => SubscriptionStatus::Awaiting(s),
+                _ => SubscriptionStatus::Other(s),
+            },
+            None => SubscriptionStatus::Unknown,
+        }
     }
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A subscription / power deal for a home / house
+pub struct Subscription {
+    /// Id of the subscription
+    pub id: String,
+    /// The legal entity holding the subscription
+    pub subscriber: Owner,
+    /// When the subscription starts, if given
+    pub valid_from: Option<DateTime<FixedOffset>>,
+    /// When the subscription ends, if given
+    pub valid_to: Option<DateTime<FixedOffset>>,
+    /// Current state of the subscription. The reason for the state is not
+    /// fetched, because the schema deprecates `statusReason` as no longer
+    /// available.
+    pub status: SubscriptionStatus,
+}
+
+impl Subscription {
+    fn new(sub: subscriptions::SubscriptionsViewerHomeSubscriptions) -> Result<Self, Error> {
+        let valid_from = match sub.valid_from {
+            Some(t) => Some(parse_time(&t)?),
+            None => None,
+        };
+        let valid_to = match sub.valid_to {
+            Some(t) => Some(parse_time(&t)?),
+            None => None,
+        };
+        Ok(Subscription {
+            id: sub.id,
+            subscriber: owner!(sub.subscriber),
+            valid_from,
+            valid_to,
+            status: SubscriptionStatus::new(sub.status),
+        })
+    }
+
+    /// The subscription is running, and `time` is within its validity period
+    pub fn is_active_at<Tz: chrono::TimeZone>(&self, time: &DateTime<Tz>) -> bool {
+        self.status == SubscriptionStatus::Running
+            && self.valid_from.is_none_or(|f| f <= *time)
+            && self.valid_to.is_none_or(|t| *time < t)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Metering point of the home / house, as registered with the grid operator
+pub struct MeteringPointData {
+    /// EAN code of the consumption metering point
+    pub consumption_ean: Option<String>,
+    /// Name of the grid company / operator
+    pub grid_company: Option<String>,
+    /// Code of the grid area
+    pub grid_area_code: Option<String>,
+    /// Code of the price area, e.g. NO1 or SE3
+    pub price_area_code: Option<String>,
+    /// EAN code of the production metering point
+    pub production_ean: Option<String>,
+    /// Which energy tax applies
+    pub energy_tax_type: Option<String>,
+    /// Which VAT rule applies
+    pub vat_type: Option<String>,
+    /// Estimated annual consumption in kWh if given
+    pub estimated_annual_consumption: Option<u32>,
+}
+
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Other features of the home / house
 pub struct Features {
     /// Realtime consumption data is available
     pub real_time_consumption_enabled: Option<bool>,
 }
 
-impl Features {
-    fn new(feat: home::HomeViewerHomeFeatures) -> Self {
-        Features {
-            real_time_consumption_enabled: feat.real_time_consumption_enabled,
-        }
-    }
-}
-
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Rough classification of price level compared to a 3 day moving avarage
 pub enum PriceLevel {
     /// Much lower than avarage
@@ -289,6 +607,7 @@ pub enum PriceLevel {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Information about price in a particular interval
 pub struct PriceInfo {
     /// Total price
@@ -305,9 +624,12 @@ pub struct PriceInfo {
     pub level: PriceLevel,
 }
 
-impl PriceInfo {
-    fn new(pinfo: price::PriceViewerHomeCurrentSubscriptionPriceInfoCurrent) -> Option<Self> {
-        let total = pinfo.total?;
+/// Build a [`PriceInfo`] from any of the generated price types. Each query
+/// module has its own copy of the `PriceLevel` enum, given as `$module`.
+macro_rules! price_info {
+    ($pinfo:expr, $module:ident) => {{
+        let pinfo = $pinfo;
+        let total = pinfo.total.ok_or(Error::MissingField("total"))?;
         let (energy, tax) = match (pinfo.energy, pinfo.tax) {
             (Some(e), Some(t)) => (e, t),
             (Some(e), None) => (e, total - e),
@@ -315,19 +637,16 @@ impl PriceInfo {
             _ => (total, 0.0),
         };
         let level = match pinfo.level {
-            Some(price::PriceLevel::VERY_CHEAP) => PriceLevel::VeryCheap,
-            Some(price::PriceLevel::CHEAP) => PriceLevel::Cheap,
-            Some(price::PriceLevel::NORMAL) => PriceLevel::Normal,
-            Some(price::PriceLevel::EXPENSIVE) => PriceLevel::Expensive,
-            Some(price::PriceLevel::VERY_EXPENSIVE) => PriceLevel::VeryExpensive,
-            Some(price::PriceLevel::Other(s)) => PriceLevel::Other(s),
+            Some($module::PriceLevel::VERY_CHEAP) => PriceLevel::VeryCheap,
+            Some($module::PriceLevel::CHEAP) => PriceLevel::Cheap,
+            Some($module::PriceLevel::NORMAL) => PriceLevel::Normal,
+            Some($module::PriceLevel::EXPENSIVE) => PriceLevel::Expensive,
+            Some($module::PriceLevel::VERY_EXPENSIVE) => PriceLevel::VeryExpensive,
+            Some($module::PriceLevel::Other(s)) => PriceLevel::Other(s),
             _ => PriceLevel::None,
         };
-        let starts_at = chrono::DateTime::parse_from_rfc3339(
-            pinfo.starts_at.ok_or("no starts_at time").ok()?.as_str(),
-        )
-        .ok()?;
-        Some(PriceInfo {
+        let starts_at = parse_time(&pinfo.starts_at.ok_or(Error::MissingField("startsAt"))?)?;
+        Ok(PriceInfo {
             total,
             energy,
             tax,
@@ -335,82 +654,146 @@ impl PriceInfo {
             currency: pinfo.currency,
             level,
         })
+    }};
+}
+
+impl PriceInfo {
+    fn new(
+        pinfo: price::PriceViewerHomeCurrentSubscriptionPriceInfoCurrent,
+    ) -> Result<Self, Error> {
+        price_info!(pinfo, price)
     }
 
     fn new_t(
         pinfo: price_today::PriceTodayViewerHomeCurrentSubscriptionPriceInfoToday,
-    ) -> Option<Self> {
-        let total = pinfo.total?;
-        let (energy, tax) = match (pinfo.energy, pinfo.tax) {
-            (Some(e), Some(t)) => (e, t),
-            (Some(e), None) => (e, total - e),
-            (None, Some(t)) => (total - t, t),
-            _ => (total, 0.0),
-        };
-        let level = match pinfo.level {
-            Some(price_today::PriceLevel::VERY_CHEAP) => PriceLevel::VeryCheap,
-            Some(price_today::PriceLevel::CHEAP) => PriceLevel::Cheap,
-            Some(price_today::PriceLevel::NORMAL) => PriceLevel::Normal,
-            Some(price_today::PriceLevel::EXPENSIVE) => PriceLevel::Expensive,
-            Some(price_today::PriceLevel::VERY_EXPENSIVE) => PriceLevel::VeryExpensive,
-            Some(price_today::PriceLevel::Other(s)) => PriceLevel::Other(s),
-            _ => PriceLevel::None,
-        };
-        let starts_at = chrono::DateTime::parse_from_rfc3339(
-            pinfo.starts_at.ok_or("no starts_at time").ok()?.as_str(),
-        )
-        .ok()?;
-        Some(PriceInfo {
-            total,
-            energy,
-            tax,
-            starts_at,
-            currency: pinfo.currency,
-            level,
-        })
+    ) -> Result<Self, Error> {
+        price_info!(pinfo, price_today)
     }
 
     fn new_f(
         pinfo: price_tomorrow::PriceTomorrowViewerHomeCurrentSubscriptionPriceInfoTomorrow,
-    ) -> Option<Self> {
-        let total = pinfo.total?;
-        let (energy, tax) = match (pinfo.energy, pinfo.tax) {
-            (Some(e), Some(t)) => (e, t),
-            (Some(e), None) => (e, total - e),
-            (None, Some(t)) => (total - t, t),
-            _ => (total, 0.0),
-        };
-        let level = match pinfo.level {
-            Some(price_tomorrow::PriceLevel::VERY_CHEAP) => PriceLevel::VeryCheap,
-            Some(price_tomorrow::PriceLevel::CHEAP) => PriceLevel::Cheap,
-            Some(price_tomorrow::PriceLevel::NORMAL) => PriceLevel::Normal,
-            Some(price_tomorrow::PriceLevel::EXPENSIVE) => PriceLevel::Expensive,
-            Some(price_tomorrow::PriceLevel::VERY_EXPENSIVE) => PriceLevel::VeryExpensive,
-            Some(price_tomorrow::PriceLevel::Other(s)) => PriceLevel::Other(s),
-            _ => PriceLevel::None,
-        };
-        let starts_at = chrono::DateTime::parse_from_rfc3339(
-            pinfo.starts_at.ok_or("no starts_at time").ok()?.as_str(),
-        )
-        .ok()?;
-        Some(PriceInfo {
-            total,
-            energy,
-            tax,
-            starts_at,
-            currency: pinfo.currency,
-            level,
+    ) -> Result<Self, Error> {
+        price_info!(pinfo, price_tomorrow)
+    }
+
+    fn new_r(
+        pinfo: price_range::PriceRangeViewerHomeCurrentSubscriptionPriceInfoRangeNodes,
+    ) -> Result<Self, Error> {
+        price_info!(pinfo, price_range)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Price compared to the other prices in the same rating, as set by the
+/// threshold percentages of [`PriceRating`]
+pub enum PriceRatingLevel {
+    /// Lower than the low threshold
+    Low,
+    /// Between the thresholds
+    Normal,
+    /// Higher than the high threshold
+    High,
+    /// Other
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Price for a single interval of a [`PriceRatingType`]
+pub struct PriceRatingEntry {
+    /// When this interval started
+    pub time: DateTime<FixedOffset>,
+    /// Energy cost component of price
+    pub energy: f64,
+    /// Total price
+    pub total: f64,
+    /// Taxes to be added to energy cost
+    pub tax: f64,
+    /// Difference from the avarage price of the rating, in percent
+    pub difference: f64,
+    /// Classification of price relative to the thresholds
+    pub level: PriceRatingLevel,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Prices rated over one resolution, e.g. hourly
+pub struct PriceRatingType {
+    /// Lowest energy price among the entries
+    pub min_energy: f64,
+    /// Highest energy price among the entries
+    pub max_energy: f64,
+    /// Lowest total price among the entries
+    pub min_total: f64,
+    /// Highest total price among the entries
+    pub max_total: f64,
+    /// The currency that is used to set price
+    pub currency: String,
+    /// Rated prices, oldest first
+    pub entries: Vec<PriceRatingEntry>,
+}
+
+impl PriceRatingType {
+    fn new(rating: price_ratings::PriceRatingType) -> Result<Self, Error> {
+        let entries = rating
+            .entries
+            .into_iter()
+            .map(|e| {
+                let level = match e.level {
+                    price_ratings::PriceRatingLevel::LOW => PriceRatingLevel::Low,
+                    price_ratings::PriceRatingLevel::NORMAL => PriceRatingLevel::Normal,
+                    price_ratings::PriceRatingLevel::HIGH => PriceRatingLevel::High,
+                    price_ratings::PriceRatingLevel::Other(s) => PriceRatingLevel::Other(s),
+                };
+                Ok(PriceRatingEntry {
+                    time: parse_time(&e.time)?,
+                    energy: e.energy,
+                    total: e.total,
+                    tax: e.tax,
+                    difference: e.difference,
+                    level,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(PriceRatingType {
+            min_energy: rating.min_energy,
+            max_energy: rating.max_energy,
+            min_total: rating.min_total,
+            max_total: rating.max_total,
+            currency: rating.currency,
+            entries,
         })
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Prices rated relative to each other. Unlike [`PriceLevel`], the levels
+/// are set by the thresholds given here.
+pub struct PriceRating {
+    /// Percentage above avarage for a price to be rated high
+    pub threshold_high: f64,
+    /// Percentage below avarage for a price to be rated low
+    pub threshold_low: f64,
+    /// Hourly prices rated against each other
+    pub hourly: PriceRatingType,
+    /// Daily prices rated against each other
+    pub daily: PriceRatingType,
+    /// Monthly prices rated against each other
+    pub monthly: PriceRatingType,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Information about a home / house
 pub struct House {
     /// Time zone where the building is located
     pub time_zone: String,
     /// Nickname given to building in App
     pub app_nickname: Option<String>,
+    /// Avatar shown for the building in App
+    pub app_avatar: HomeAvatar,
     /// Size of house in square meters if given
     pub size: Option<u32>,
     /// Type of building
@@ -425,11 +808,124 @@ pub struct House {
     pub main_fuse_size: Option<u32>,
     /// Address information
     pub address: Option<Address>,
+    /// Owner of the building
+    pub owner: Option<Owner>,
+    /// Metering point information
+    pub metering_point_data: Option<MeteringPointData>,
     /// Other features
     pub features: Features,
 }
 
+#[derive(Debug, Clone, Default)]
+/// Changes to the writable profile fields of a home, see
+/// [`TibberSession::update_home()`]. Fields that are not set are left unchanged.
+pub struct HomeUpdate {
+    app_nickname: Option<String>,
+    app_avatar: Option<HomeAvatar>,
+    size: Option<u32>,
+    house_type: Option<HouseType>,
+    number_of_residents: Option<u32>,
+    primary_heating_source: Option<HeatingSource>,
+    has_ventilation_system: Option<bool>,
+    main_fuse_size: Option<u32>,
+}
+
+impl HomeUpdate {
+    /// Create an empty update
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set nickname given to building in App
+    pub fn app_nickname(mut self, app_nickname: impl Into<String>) -> Self {
+        self.app_nickname = Some(app_nickname.into());
+        self
+    }
+
+    /// Set avatar shown for the building in App
+    pub fn app_avatar(mut self, app_avatar: HomeAvatar) -> Self {
+        self.app_avatar = Some(app_avatar);
+        self
+    }
+
+    /// Set size of house in square meters
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Set type of building
+    pub fn house_type(mut self, house_type: HouseType) -> Self {
+        self.house_type = Some(house_type);
+        self
+    }
+
+    /// Set number of residents
+    pub fn number_of_residents(mut self, number_of_residents: u32) -> Self {
+        self.number_of_residents = Some(number_of_residents);
+        self
+    }
+
+    /// Set primary heating source
+    pub fn primary_heating_source(mut self, primary_heating_source: HeatingSource) -> Self {
+        self.primary_heating_source = Some(primary_heating_source);
+        self
+    }
+
+    /// Set whether the building has a ventilation system
+    pub fn has_ventilation_system(mut self, has_ventilation_system: bool) -> Self {
+        self.has_ventilation_system = Some(has_ventilation_system);
+        self
+    }
+
+    /// Set size of main fuse in Amperes
+    pub fn main_fuse_size(mut self, main_fuse_size: u32) -> Self {
+        self.main_fuse_size = Some(main_fuse_size);
+        self
+    }
+
+    fn variables(self, home_id: &HomeId) -> Result<update_home::Variables, Error> {
+        fn int(value: Option<u32>, what: &'static str) -> Result<Option<i64>, Error> {
+            match value {
+                Some(v) if v > i32::MAX as u32 => Err(Error::InvalidInput(what)),
+                v => Ok(v.map(i64::from)),
+            }
+        }
+        if self.app_nickname.is_none()
+            && self.app_avatar.is_none()
+            && self.size.is_none()
+            && self.house_type.is_none()
+            && self.number_of_residents.is_none()
+            && self.primary_heating_source.is_none()
+            && self.has_ventilation_system.is_none()
+            && self.main_fuse_size.is_none()
+        {
+            return Err(Error::InvalidInput("no fields to update"));
+        }
+        Ok(update_home::Variables {
+            input: update_home::UpdateHomeInput {
+                home_id: home_id.0.to_owned(),
+                app_nickname: self.app_nickname,
+                app_avatar: self.app_avatar.map(HomeAvatar::input),
+                size: int(self.size, "size is too large")?,
+                type_: self.house_type.map(HouseType::input),
+                number_of_residents: int(
+                    self.number_of_residents,
+                    "number of residents is too large",
+                )?,
+                primary_heating_source: self
+                    .primary_heating_source
+                    .map(HeatingSource::input)
+                    .transpose()?,
+                has_ventilation_system: self.has_ventilation_system,
+                main_fuse_size: int(self.main_fuse_size, "main fuse size is too large")?,
+            },
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Resolution of time when requestiong consumption data
 pub enum TimeResolution {
     /// Hourely intervals
@@ -444,7 +940,31 @@ pub enum TimeResolution {
     Annual,
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Resolution of time when requesting historical prices
+pub enum PriceResolution {
+    /// Hourly intervals
+    Hourly,
+    /// Daily intervals
+    Daily,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Window of time to fetch history for. Requests are split into pages
+/// behind the scenes, so a span can cover months of hourly data.
+pub enum Span {
+    /// The last n intervals
+    Last(u32),
+    /// All intervals starting at or after the given time
+    Since(DateTime<FixedOffset>),
+    /// All intervals starting at or after the first time, and before the second
+    Between(DateTime<FixedOffset>, DateTime<FixedOffset>),
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Measurement if energy with appropriate units
 pub enum EnergyUnits {
     #[allow(non_camel_case_types)]
@@ -455,29 +975,42 @@ pub enum EnergyUnits {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Consumption data
 pub struct Consumption {
     /// Start of interval
     pub from: DateTime<FixedOffset>,
     /// End of interval
     pub to: DateTime<FixedOffset>,
-    /// Total price
-    pub cost: f64,
-    /// Price pr unit
-    pub unit_price: f64,
-    /// Tax pr unit
-    pub unit_price_vat: f64,
+    /// Energy cost of the interval. Only missing in lenient mode, see
+    /// [`TibberSessionBuilder::lenient()`]
+    pub cost: Option<f64>,
+    /// Total cost of the interval. Deprecated by the API, which now gives
+    /// the same value as `cost`
+    pub total_cost: Option<f64>,
+    /// Cost pr unit. Deprecated by the API in favour of `cost`
+    pub unit_cost: Option<f64>,
+    /// Price pr unit. Only missing in lenient mode
+    pub unit_price: Option<f64>,
+    /// Tax pr unit. Only missing in lenient mode
+    pub unit_price_vat: Option<f64>,
+    /// The currency of the costs
+    pub currency: Option<String>,
     /// Type of units and units
     pub energy: EnergyUnits,
 }
 
-impl Consumption {
-    fn new(
-        node: consumption_history::ConsumptionHistoryViewerHomeConsumptionNodes,
-    ) -> Option<Self> {
-        let cost = node.cost?;
-        let unit_price = node.unit_price?;
-        let unit_price_vat = node.unit_price_vat?;
+/// Build a [`Consumption`] from any of the generated consumption node types
+///
+/// Intervals without cost are left out unless `$lenient` is set.
+macro_rules! consumption {
+    ($node:expr, $lenient:expr) => {{
+        let node = $node;
+        if !$lenient
+            && (node.cost.is_none() || node.unit_price.is_none() || node.unit_price_vat.is_none())
+        {
+            return Ok(None);
+        }
         let energy = match node.consumption_unit {
             Some(s) if s.as_str() == "kWh" => match node.consumption {
                 Some(c) => EnergyUnits::kWh(c),
@@ -485,41 +1018,71 @@ impl Consumption {
             },
             _ => EnergyUnits::None,
         };
-        let from = DateTime::parse_from_rfc3339(node.from.as_str()).ok()?;
-        let to = DateTime::parse_from_rfc3339(node.to.as_str()).ok()?;
-        Some(Consumption {
+        let from = parse_time(&node.from)?;
+        let to = parse_time(&node.to)?;
+        #[allow(deprecated)]
+        Ok(Some(Consumption {
             from,
             to,
-            cost,
-            unit_price,
-            unit_price_vat,
+            cost: node.cost,
+            total_cost: node.total_cost,
+            unit_cost: node.unit_cost,
+            unit_price: node.unit_price,
+            unit_price_vat: node.unit_price_vat,
+            currency: node.currency,
             energy,
-        })
+        }))
+    }};
+}
+
+impl Consumption {
+    fn new(
+        node: consumption_history::ConsumptionHistoryViewerHomeConsumptionNodes,
+        lenient: bool,
+    ) -> Result<Option<Self>, Error> {
+        consumption!(node, lenient)
+    }
+
+    fn new_r(
+        node: consumption_range::ConsumptionRangeViewerHomeConsumptionEdgesNode,
+        lenient: bool,
+    ) -> Result<Option<Self>, Error> {
+        consumption!(node, lenient)
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Production data
 pub struct Production {
     /// Start of interval
     pub from: DateTime<FixedOffset>,
     /// End of interval
     pub to: DateTime<FixedOffset>,
-    /// Total price
-    pub profit: f64,
-    /// Price pr unit
-    pub unit_price: f64,
-    /// Tax pr unit
-    pub unit_price_vat: f64,
+    /// Total price. Only missing in lenient mode, see
+    /// [`TibberSessionBuilder::lenient()`]
+    pub profit: Option<f64>,
+    /// Price pr unit. Only missing in lenient mode
+    pub unit_price: Option<f64>,
+    /// Tax pr unit. Only missing in lenient mode
+    pub unit_price_vat: Option<f64>,
+    /// The currency of the profit
+    pub currency: Option<String>,
     /// Type of units and units
     pub energy: EnergyUnits,
 }
 
-impl Production {
-    fn new(node: production_history::ProductionHistoryViewerHomeProductionNodes) -> Option<Self> {
-        let profit = node.profit?;
-        let unit_price = node.unit_price?;
-        let unit_price_vat = node.unit_price_vat?;
+/// Build a [`Production`] from any of the generated production node types
+///
+/// Intervals without profit are left out unless `$lenient` is set.
+macro_rules! production {
+    ($node:expr, $lenient:expr) => {{
+        let node = $node;
+        if !$lenient
+            && (node.profit.is_none() || node.unit_price.is_none() || node.unit_price_vat.is_none())
+        {
+            return Ok(None);
+        }
         let energy = match node.production_unit {
             Some(s) if s.as_str() == "kWh" => match node.production {
                 Some(c) => EnergyUnits::kWh(c),
@@ -527,36 +1090,222 @@ impl Production {
             },
             _ => EnergyUnits::None,
         };
-        let from = DateTime::parse_from_rfc3339(node.from.as_str()).ok()?;
-        let to = DateTime::parse_from_rfc3339(node.to.as_str()).ok()?;
-        Some(Production {
+        let from = parse_time(&node.from)?;
+        let to = parse_time(&node.to)?;
+        Ok(Some(Production {
             from,
             to,
-            profit,
-            unit_price,
-            unit_price_vat,
+            profit: node.profit,
+            unit_price: node.unit_price,
+            unit_price_vat: node.unit_price_vat,
+            currency: node.currency,
             energy,
+        }))
+    }};
+}
+
+impl Production {
+    fn new(
+        node: production_history::ProductionHistoryViewerHomeProductionNodes,
+        lenient: bool,
+    ) -> Result<Option<Self>, Error> {
+        production!(node, lenient)
+    }
+
+    fn new_r(
+        node: production_range::ProductionRangeViewerHomeProductionEdgesNode,
+        lenient: bool,
+    ) -> Result<Option<Self>, Error> {
+        production!(node, lenient)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Consumption history together with totals calculated by the API
+pub struct ConsumptionSummary {
+    /// The consumption in each interval
+    pub nodes: Vec<Consumption>,
+    /// Total cost of all intervals, as invoiced
+    pub total_cost: Option<f64>,
+    /// Energy cost of all intervals. Deprecated by the API as redundant, it
+    /// gives the same value as `total_cost`
+    pub energy_cost: Option<f64>,
+    /// Total consumption of all intervals (kWh)
+    pub total_consumption: Option<f64>,
+    /// The currency of the costs
+    pub currency: Option<String>,
+    /// Number of intervals
+    pub count: Option<u32>,
+    /// Number of intervals left out for lack of data
+    pub filtered: u32,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Production history together with totals calculated by the API
+pub struct ProductionSummary {
+    /// The production in each interval
+    pub nodes: Vec<Production>,
+    /// Total profit of all intervals
+    pub total_profit: Option<f64>,
+    /// Total production of all intervals (kWh)
+    pub total_production: Option<f64>,
+    /// The currency of the profit
+    pub currency: Option<String>,
+    /// Number of intervals
+    pub count: Option<u32>,
+    /// Number of intervals left out for lack of data
+    pub filtered: u32,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Real time measurement from a power meter (Pulse, Watty etc.)
+pub struct LiveMeasurement {
+    /// When the measurement was taken
+    pub timestamp: DateTime<FixedOffset>,
+    /// Consumption at the moment (Watt)
+    pub power: f64,
+    /// Last meter active import register state (kWh)
+    pub last_meter_consumption: Option<f64>,
+    /// kWh consumed since midnight
+    pub accumulated_consumption: f64,
+    /// kWh produced since midnight
+    pub accumulated_production: f64,
+    /// kWh consumed since since last hour shift
+    pub accumulated_consumption_last_hour: f64,
+    /// kWh produced since last hour shift
+    pub accumulated_production_last_hour: f64,
+    /// Accumulated cost since midnight, requires active Tibber power deal
+    pub accumulated_cost: Option<f64>,
+    /// Accumulated reward since midnight, requires active Tibber power deal
+    pub accumulated_reward: Option<f64>,
+    /// Currency of displayed cost, requires active Tibber power deal
+    pub currency: Option<String>,
+    /// Min consumption since midnight (Watt)
+    pub min_power: f64,
+    /// Average consumption since midnight (Watt)
+    pub average_power: f64,
+    /// Peak consumption since midnight (Watt)
+    pub max_power: f64,
+    /// Net production (A-) at the moment (Watt)
+    pub power_production: Option<f64>,
+    /// Reactive consumption (Q+) at the moment (kVAr)
+    pub power_reactive: Option<f64>,
+    /// Net reactive production (Q-) at the moment (kVAr)
+    pub power_production_reactive: Option<f64>,
+    /// Min net production since midnight (Watt)
+    pub min_power_production: Option<f64>,
+    /// Max net production since midnight (Watt)
+    pub max_power_production: Option<f64>,
+    /// Last meter active export register state (kWh)
+    pub last_meter_production: Option<f64>,
+    /// Power factor (active power / apparent power)
+    pub power_factor: Option<f64>,
+    /// Voltage on phase 1, 2 and 3 (Volt)
+    pub voltage: [Option<f64>; 3],
+    /// Current on L1, L2 and L3 (Ampere)
+    pub current: [Option<f64>; 3],
+    /// Device signal strength (Pulse - dB; Watty - percent)
+    pub signal_strength: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Manual meter reading as registered by Tibber
+pub struct MeterReading {
+    /// The home the reading was registered for
+    pub home_id: HomeId,
+    /// When the meter was read
+    pub time: Option<DateTime<FixedOffset>>,
+    /// Meter register value (kWh)
+    pub reading: i64,
+}
+
+impl MeterReading {
+    fn new(data: send_meter_reading::ResponseData) -> Result<Self, Error> {
+        let response = data.send_meter_reading;
+        let time = match response.time {
+            Some(t) => Some(parse_time(&t)?),
+            None => None,
+        };
+        Ok(MeterReading {
+            home_id: HomeId(response.home_id),
+            time,
+            reading: response.reading,
         })
     }
 }
 
-/// A tibber session, can be shared among threads, only holds the API token
-pub struct TibberSession {
-    authentication: String,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Screen in the Tibber App to open when a push notification is tapped
+pub enum AppScreen {
+    /// Home screen
+    Home,
+    /// Reports
+    Reports,
+    /// Consumption
+    Consumption,
+    /// Comparison with similar homes
+    Comparison,
+    /// Breakdown of consumption
+    Disaggregation,
+    /// Profile of the home
+    HomeProfile,
+    /// Profile of the customer
+    CustomerProfile,
+    /// Manual meter reading
+    MeterReading,
+    /// Notifications
+    Notifications,
+    /// Invoices
+    Invoices,
 }
 
-impl TibberSession {
-    /// Create a new session object
-    pub fn new(authentication: String) -> Self {
-        TibberSession { authentication }
+impl AppScreen {
+    fn input(self) -> send_push_notification::AppScreen {
+        match self {
+            AppScreen::Home => send_push_notification::AppScreen::HOME,
+            AppScreen::Reports => send_push_notification::AppScreen::REPORTS,
+            AppScreen::Consumption => send_push_notification::AppScreen::CONSUMPTION,
+            AppScreen::Comparison => send_push_notification::AppScreen::COMPARISON,
+            AppScreen::Disaggregation => send_push_notification::AppScreen::DISAGGREGATION,
+            AppScreen::HomeProfile => send_push_notification::AppScreen::HOME_PROFILE,
+            AppScreen::CustomerProfile => send_push_notification::AppScreen::CUSTOMER_PROFILE,
+            AppScreen::MeterReading => send_push_notification::AppScreen::METER_READING,
+            AppScreen::Notifications => send_push_notification::AppScreen::NOTIFICATIONS,
+            AppScreen::Invoices => send_push_notification::AppScreen::INVOICES,
+        }
     }
+}
 
-    /// Get information about the logged in user
-    pub fn get_user(&self) -> Result<User, Box<dyn std::error::Error>> {
-        let viewer = fetch_data::<Viewer>(self.authentication.as_str(), viewer::Variables {})?;
-        let login = viewer.viewer.login.ok_or("No login")?;
-        let user_id = viewer.viewer.user_id.ok_or("No UserId")?;
-        let name = viewer.viewer.name.ok_or("No Name")?;
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Outcome of sending a push notification
+pub struct PushNotification {
+    /// The notification was accepted
+    pub successful: bool,
+    /// Number of devices the notification was delivered to
+    pub pushed_to_number_of_devices: u32,
+}
+
+impl PushNotification {
+    fn new(data: send_push_notification::ResponseData) -> Self {
+        let response = data.send_push_notification;
+        PushNotification {
+            successful: response.successful,
+            pushed_to_number_of_devices: response.pushed_to_number_of_devices.max(0) as u32,
+        }
+    }
+}
+
+impl User {
+    fn new(viewer: viewer::ResponseData) -> Result<Self, Error> {
+        let login = viewer.viewer.login.ok_or(Error::MissingField("login"))?;
+        let user_id = viewer.viewer.user_id.ok_or(Error::MissingField("userId"))?;
+        let name = viewer.viewer.name.ok_or(Error::MissingField("name"))?;
         let account_type = viewer.viewer.account_type;
         let homes = viewer
             .viewer
@@ -573,120 +1322,660 @@ impl TibberSession {
             homes,
         })
     }
+}
 
-    /// Get information about a particular home / house
-    pub fn get_home(&self, home_id: &HomeId) -> Result<House, Box<dyn std::error::Error>> {
-        let id = home_id.0.to_owned();
-        let home = fetch_data::<Home>(self.authentication.as_str(), home::Variables { id })?;
-        let time_zone = home.viewer.home.time_zone;
-        let app_nickname = home.viewer.home.app_nickname;
-        let size = match home.viewer.home.size {
-            Some(s) if s >= 0 => Some(s as u32),
-            _ => None,
+/// Build a [`House`] from any of the generated home types. Each query module
+/// has its own copy of the enums, given as `$module`.
+macro_rules! house {
+    ($home:expr, $module:ident) => {{
+        let home = $home;
+        let app_avatar = match home.app_avatar {
+            $module::HomeAvatar::APARTMENT => HomeAvatar::Apartment,
+            $module::HomeAvatar::ROWHOUSE => HomeAvatar::RowHouse,
+            $module::HomeAvatar::FLOORHOUSE1 => HomeAvatar::FloorHouse1,
+            $module::HomeAvatar::FLOORHOUSE2 => HomeAvatar::FloorHouse2,
+            $module::HomeAvatar::FLOORHOUSE3 => HomeAvatar::FloorHouse3,
+            $module::HomeAvatar::COTTAGE => HomeAvatar::Cottage,
+            $module::HomeAvatar::CASTLE => HomeAvatar::Castle,
+            $module::HomeAvatar::Other(s) => HomeAvatar::Other(s),
         };
-        let house_type = HouseType::new(home.viewer.home.type_);
-        let number_of_residents = match home.viewer.home.number_of_residents {
-            Some(n) if n >= 0 => Some(n as u32),
-            _ => None,
+        let house_type = match home.type_ {
+            $module::HomeType::APARTMENT => HouseType::Apartment,
+            $module::HomeType::ROWHOUSE => HouseType::RowHouse,
+            $module::HomeType::HOUSE => HouseType::House,
+            $module::HomeType::COTTAGE => HouseType::Cottage,
+            $module::HomeType::Other(s) => HouseType::Other(s),
         };
-        let primary_heating_source = HeatingSource::new(home.viewer.home.primary_heating_source);
-        let has_ventilation_system = home.viewer.home.has_ventilation_system;
-        let main_fuse_size = match home.viewer.home.main_fuse_size {
-            Some(s) if s >= 0 => Some(s as u32),
-            _ => None,
+        let primary_heating_source = match home.primary_heating_source {
+            Some($module::HeatingSource::AIR2AIR_HEATPUMP) => HeatingSource::Air2AairHeatPump,
+            Some($module::HeatingSource::ELECTRICITY) => HeatingSource::Electricity,
+            Some($module::HeatingSource::GROUND) => HeatingSource::Ground,
+            Some($module::HeatingSource::DISTRICT_HEATING) => HeatingSource::DistrictHeating,
+            Some($module::HeatingSource::ELECTRIC_BOILER) => HeatingSource::ElectricBoiler,
+            Some($module::HeatingSource::AIR2WATER_HEATPUMP) => HeatingSource::Air2WaterHeatPump,
+            Some($module::HeatingSource::OTHER) => HeatingSource::Other(None),
+            Some($module::HeatingSource::Other(s)) => HeatingSource::Other(Some(s)),
+            None => HeatingSource::Unknown,
         };
-        let address = home.viewer.home.address.map(Address::new);
-        let features = match home.viewer.home.features {
-            Some(f) => Features::new(f),
+        let metering_point_data = home.metering_point_data.map(|mpd| MeteringPointData {
+            consumption_ean: mpd.consumption_ean,
+            grid_company: mpd.grid_company,
+            grid_area_code: mpd.grid_area_code,
+            price_area_code: mpd.price_area_code,
+            production_ean: mpd.production_ean,
+            energy_tax_type: mpd.energy_tax_type,
+            vat_type: mpd.vat_type,
+            estimated_annual_consumption: match mpd.estimated_annual_consumption {
+                Some(e) if e >= 0 => Some(e as u32),
+                _ => None,
+            },
+        });
+        let features = match home.features {
+            Some(f) => Features {
+                real_time_consumption_enabled: f.real_time_consumption_enabled,
+            },
             _ => Default::default(),
         };
 
-        Ok(House {
-            time_zone,
-            app_nickname,
-            size,
+        House {
+            time_zone: home.time_zone,
+            app_nickname: home.app_nickname,
+            app_avatar,
+            size: match home.size {
+                Some(s) if s >= 0 => Some(s as u32),
+                _ => None,
+            },
             house_type,
-            number_of_residents,
+            number_of_residents: match home.number_of_residents {
+                Some(n) if n >= 0 => Some(n as u32),
+                _ => None,
+            },
             primary_heating_source,
-            has_ventilation_system,
-            main_fuse_size,
-            address,
+            has_ventilation_system: home.has_ventilation_system,
+            main_fuse_size: match home.main_fuse_size {
+                Some(s) if s >= 0 => Some(s as u32),
+                _ => None,
+            },
+            address: home.address.map(|a| address!(a)),
+            owner: home.owner.map(|o| owner!(o)),
+            metering_point_data,
             features,
-        })
+        }
+    }};
+}
+
+impl House {
+    fn new(home: home::ResponseData) -> Self {
+        house!(home.viewer.home, home)
     }
 
-    /// Get Current price information for a particular house / home
-    pub fn get_current_price(
+    fn updated(update: update_home::ResponseData) -> Self {
+        house!(update.update_home, update_home)
+    }
+}
+
+fn current_price(price: price::ResponseData) -> Result<PriceInfo, Error> {
+    let price = PriceInfo::new(
+        price
+            .viewer
+            .home
+            .current_subscription
+            .ok_or(Error::MissingField("currentSubscription"))?
+            .price_info
+            .ok_or(Error::MissingField("priceInfo"))?
+            .current
+            .ok_or(Error::MissingField("current"))?,
+    )?;
+    Ok(price)
+}
+
+fn prices_today(price: price_today::ResponseData) -> Result<Vec<PriceInfo>, Error> {
+    let prices = price
+        .viewer
+        .home
+        .current_subscription
+        .ok_or(Error::MissingField("currentSubscription"))?
+        .price_info
+        .ok_or(Error::MissingField("priceInfo"))?
+        .today;
+    let prices = prices
+        .into_iter()
+        .flatten()
+        .map(PriceInfo::new_t)
+        .collect::<Result<_, _>>()?;
+    Ok(prices)
+}
+
+fn prices_tomorrow(price: price_tomorrow::ResponseData) -> Result<Vec<PriceInfo>, Error> {
+    let prices = price
+        .viewer
+        .home
+        .current_subscription
+        .ok_or(Error::MissingField("currentSubscription"))?
+        .price_info
+        .ok_or(Error::MissingField("priceInfo"))?
+        .tomorrow;
+    let prices = prices
+        .into_iter()
+        .flatten()
+        .map(PriceInfo::new_f)
+        .collect::<Result<_, _>>()?;
+    Ok(prices)
+}
+
+fn consumption_variables(
+    home_id: &HomeId,
+    resolution: TimeResolution,
+    last: u32,
+) -> consumption_history::Variables {
+    let id = home_id.0.to_owned();
+    let resolution = match resolution {
+        TimeResolution::Hourly => consumption_history::EnergyResolution::HOURLY,
+        TimeResolution::Daily => consumption_history::EnergyResolution::DAILY,
+        TimeResolution::Weekly => consumption_history::EnergyResolution::WEEKLY,
+        TimeResolution::Monthly => consumption_history::EnergyResolution::MONTHLY,
+        TimeResolution::Annual => consumption_history::EnergyResolution::ANNUAL,
+    };
+    consumption_history::Variables {
+        id,
+        resolution,
+        num: last.into(),
+    }
+}
+
+fn consumption_summary(
+    history: consumption_history::ResponseData,
+    lenient: bool,
+) -> Result<ConsumptionSummary, Error> {
+    let history = history
+        .viewer
+        .home
+        .consumption
+        .ok_or(Error::MissingField("consumption"))?;
+    let nodes = history
+        .nodes
+        .ok_or(Error::MissingField("nodes"))?
+        .into_iter()
+        .flatten()
+        .map(|node| Consumption::new(node, lenient))
+        .filter_map(Result::transpose)
+        .collect::<Result<_, _>>()?;
+    let info = history.page_info;
+    #[allow(deprecated)]
+    Ok(ConsumptionSummary {
+        nodes,
+        total_cost: info.total_cost,
+        energy_cost: info.energy_cost,
+        total_consumption: info.total_consumption,
+        currency: info.currency,
+        count: info.count.and_then(|c| u32::try_from(c).ok()),
+        filtered: u32::try_from(info.filtered).unwrap_or(0),
+    })
+}
+
+fn consumption_nodes(
+    history: consumption_history::ResponseData,
+    lenient: bool,
+) -> Result<Vec<Consumption>, Error> {
+    Ok(consumption_summary(history, lenient)?.nodes)
+}
+
+fn production_variables(
+    home_id: &HomeId,
+    resolution: TimeResolution,
+    last: u32,
+) -> production_history::Variables {
+    let id = home_id.0.to_owned();
+    let resolution = match resolution {
+        TimeResolution::Hourly => production_history::EnergyResolution::HOURLY,
+        TimeResolution::Daily => production_history::EnergyResolution::DAILY,
+        TimeResolution::Weekly => production_history::EnergyResolution::WEEKLY,
+        TimeResolution::Monthly => production_history::EnergyResolution::MONTHLY,
+        TimeResolution::Annual => production_history::EnergyResolution::ANNUAL,
+    };
+    production_history::Variables {
+        id,
+        resolution,
+        num: last.into(),
+    }
+}
+
+fn production_summary(
+    history: production_history::ResponseData,
+    lenient: bool,
+) -> Result<ProductionSummary, Error> {
+    let history = history
+        .viewer
+        .home
+        .production
+        .ok_or(Error::MissingField("production"))?;
+    let nodes = history
+        .nodes
+        .ok_or(Error::MissingField("nodes"))?
+        .into_iter()
+        .flatten()
+        .map(|node| Production::new(node, lenient))
+        .filter_map(Result::transpose)
+        .collect::<Result<_, _>>()?;
+    let info = history.page_info;
+    Ok(ProductionSummary {
+        nodes,
+        total_profit: info.total_profit,
+        total_production: info.total_production,
+        currency: info.currency,
+        count: info.count.and_then(|c| u32::try_from(c).ok()),
+        filtered: u32::try_from(info.filtered).unwrap_or(0),
+    })
+}
+
+fn production_nodes(
+    history: production_history::ResponseData,
+    lenient: bool,
+) -> Result<Vec<Production>, Error> {
+    Ok(production_summary(history, lenient)?.nodes)
+}
+
+fn consumption_range_variables(
+    home_id: &HomeId,
+    resolution: &TimeResolution,
+    page: paging::Page,
+    filter_empty_nodes: bool,
+) -> consumption_range::Variables {
+    let id = home_id.0.to_owned();
+    let resolution = match resolution {
+        TimeResolution::Hourly => consumption_range::EnergyResolution::HOURLY,
+        TimeResolution::Daily => consumption_range::EnergyResolution::DAILY,
+        TimeResolution::Weekly => consumption_range::EnergyResolution::WEEKLY,
+        TimeResolution::Monthly => consumption_range::EnergyResolution::MONTHLY,
+        TimeResolution::Annual => consumption_range::EnergyResolution::ANNUAL,
+    };
+    consumption_range::Variables {
+        id,
+        resolution,
+        first: page.first,
+        last: page.last,
+        before: page.before,
+        after: page.after,
+        filter_empty_nodes: Some(filter_empty_nodes),
+    }
+}
+
+fn consumption_range_page(
+    history: consumption_range::ResponseData,
+    lenient: bool,
+) -> Result<(Vec<Consumption>, paging::PageInfo), Error> {
+    let history = history
+        .viewer
+        .home
+        .consumption
+        .ok_or(Error::MissingField("consumption"))?;
+    let nodes = history
+        .edges
+        .ok_or(Error::MissingField("edges"))?
+        .into_iter()
+        .flatten()
+        .map(|edge| Consumption::new_r(edge.node, lenient))
+        .filter_map(Result::transpose)
+        .collect::<Result<_, _>>()?;
+    let info = paging::PageInfo {
+        start_cursor: history.page_info.start_cursor,
+        end_cursor: history.page_info.end_cursor,
+        has_next_page: history.page_info.has_next_page.unwrap_or(false),
+        has_previous_page: history.page_info.has_previous_page.unwrap_or(false),
+    };
+    Ok((nodes, info))
+}
+
+fn production_range_variables(
+    home_id: &HomeId,
+    resolution: &TimeResolution,
+    page: paging::Page,
+    filter_empty_nodes: bool,
+) -> production_range::Variables {
+    let id = home_id.0.to_owned();
+    let resolution = match resolution {
+        TimeResolution::Hourly => production_range::EnergyResolution::HOURLY,
+        TimeResolution::Daily => production_range::EnergyResolution::DAILY,
+        TimeResolution::Weekly => production_range::EnergyResolution::WEEKLY,
+        TimeResolution::Monthly => production_range::EnergyResolution::MONTHLY,
+        TimeResolution::Annual => production_range::EnergyResolution::ANNUAL,
+    };
+    production_range::Variables {
+        id,
+        resolution,
+        first: page.first,
+        last: page.last,
+        before: page.before,
+        after: page.after,
+        filter_empty_nodes: Some(filter_empty_nodes),
+    }
+}
+
+fn production_range_page(
+    history: production_range::ResponseData,
+    lenient: bool,
+) -> Result<(Vec<Production>, paging::PageInfo), Error> {
+    let history = history
+        .viewer
+        .home
+        .production
+        .ok_or(Error::MissingField("production"))?;
+    let nodes = history
+        .edges
+        .ok_or(Error::MissingField("edges"))?
+        .into_iter()
+        .flatten()
+        .map(|edge| Production::new_r(edge.node, lenient))
+        .filter_map(Result::transpose)
+        .collect::<Result<_, _>>()?;
+    let info = paging::PageInfo {
+        start_cursor: history.page_info.start_cursor,
+        end_cursor: history.page_info.end_cursor,
+        has_next_page: history.page_info.has_next_page.unwrap_or(false),
+        has_previous_page: history.page_info.has_previous_page.unwrap_or(false),
+    };
+    Ok((nodes, info))
+}
+
+fn meter_reading_variables(
+    home_id: &HomeId,
+    time: DateTime<FixedOffset>,
+    reading: u32,
+) -> Result<send_meter_reading::Variables, Error> {
+    if time > chrono::Utc::now() {
+        return Err(Error::InvalidInput("meter reading time is in the future"));
+    }
+    if reading > i32::MAX as u32 {
+        return Err(Error::InvalidInput("meter reading is too large"));
+    }
+    Ok(send_meter_reading::Variables {
+        input: send_meter_reading::MeterReadingInput {
+            home_id: home_id.0.to_owned(),
+            time: Some(time.to_rfc3339()),
+            reading: reading.into(),
+        },
+    })
+}
+
+fn push_notification_variables(
+    title: &str,
+    message: &str,
+    screen: AppScreen,
+) -> Result<send_push_notification::Variables, Error> {
+    if message.trim().is_empty() {
+        return Err(Error::InvalidInput("push notification message is empty"));
+    }
+    Ok(send_push_notification::Variables {
+        input: send_push_notification::PushNotificationInput {
+            title: Some(title.to_owned()).filter(|t| !t.is_empty()),
+            message: message.to_owned(),
+            screen_to_open: Some(screen.input()),
+        },
+    })
+}
+
+fn subscription_list(data: subscriptions::ResponseData) -> Result<Vec<Subscription>, Error> {
+    data.viewer
+        .home
+        .subscriptions
+        .into_iter()
+        .flatten()
+        .map(Subscription::new)
+        .collect()
+}
+
+fn price_range_variables(
+    home_id: &HomeId,
+    resolution: &PriceResolution,
+    page: paging::Page,
+) -> price_range::Variables {
+    let id = home_id.0.to_owned();
+    let resolution = match resolution {
+        PriceResolution::Hourly => price_range::PriceResolution::HOURLY,
+        PriceResolution::Daily => price_range::PriceResolution::DAILY,
+    };
+    price_range::Variables {
+        id,
+        resolution,
+        first: page.first,
+        last: page.last,
+        before: page.before,
+        after: page.after,
+    }
+}
+
+fn price_range_page(
+    price: price_range::ResponseData,
+) -> Result<(Vec<PriceInfo>, paging::PageInfo), Error> {
+    let range = price
+        .viewer
+        .home
+        .current_subscription
+        .ok_or(Error::MissingField("currentSubscription"))?
+        .price_info
+        .ok_or(Error::MissingField("priceInfo"))?
+        .range
+        .ok_or(Error::MissingField("range"))?;
+    let prices = range
+        .nodes
+        .into_iter()
+        .flatten()
+        .map(PriceInfo::new_r)
+        .collect::<Result<_, _>>()?;
+    let info = paging::PageInfo {
+        start_cursor: range.page_info.start_cursor,
+        end_cursor: range.page_info.end_cursor,
+        has_next_page: range.page_info.has_next_page.unwrap_or(false),
+        has_previous_page: range.page_info.has_previous_page.unwrap_or(false),
+    };
+    Ok((prices, info))
+}
+
+fn price_rating(rating: price_ratings::ResponseData) -> Result<PriceRating, Error> {
+    let rating = rating
+        .viewer
+        .home
+        .current_subscription
+        .ok_or(Error::MissingField("currentSubscription"))?
+        .price_rating
+        .ok_or(Error::MissingField("priceRating"))?;
+    Ok(PriceRating {
+        threshold_high: rating.threshold_percentages.high,
+        threshold_low: rating.threshold_percentages.low,
+        hourly: PriceRatingType::new(rating.hourly)?,
+        daily: PriceRatingType::new(rating.daily)?,
+        monthly: PriceRatingType::new(rating.monthly)?,
+    })
+}
+
+/// A tibber session, can be shared among threads. Holds the API token and
+/// the transport that is reused for all calls.
+pub struct TibberSession<T: Transport = DefaultTransport> {
+    authentication: String,
+    transport: Arc<T>,
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    websocket_url: String,
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    user_agent: String,
+    partial: Option<Arc<Mutex<Vec<GraphQLError>>>>,
+    lenient: bool,
+    cache: Option<cache::Cache>,
+}
+
+impl<T: Transport> Clone for TibberSession<T> {
+    fn clone(&self) -> Self {
+        TibberSession {
+            authentication: self.authentication.clone(),
+            transport: self.transport.clone(),
+            websocket_url: self.websocket_url.clone(),
+            user_agent: self.user_agent.clone(),
+            partial: self.partial.clone(),
+            lenient: self.lenient,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl TibberSession {
+    /// Create a new session object with default settings
+    ///
+    /// # Panics
+    ///
+    /// If the HTTP client cannot be created, see [`Self::builder()`] to
+    /// handle this as an error.
+    pub fn new(authentication: String) -> Self {
+        Self::builder(authentication)
+            .build()
+            .expect("Could not create HTTP client")
+    }
+
+    /// Create a session with custom endpoint, user agent, timeouts etc.
+    pub fn builder(authentication: String) -> TibberSessionBuilder {
+        TibberSessionBuilder::new(authentication)
+    }
+}
+
+impl<T: Transport> TibberSession<T> {
+    /// Create a session that sends all requests through `transport`
+    pub fn with_transport(authentication: String, transport: T) -> Self {
+        TibberSessionBuilder::new(authentication).build_with_transport(transport)
+    }
+
+    fn fetch_data<Q: GraphQLQuery>(
+        &self,
+        variables: <Q as GraphQLQuery>::Variables,
+    ) -> Result<<Q as GraphQLQuery>::ResponseData, Error> {
+        let body = serde_json::to_string(&Q::build_query(variables))?;
+        let (status, response) = self.transport.execute(&self.authentication, &body)?;
+        check_status(status, &response)?;
+        response_data(serde_json::from_str(&response)?, self.partial.as_deref())
+    }
+
+    /// Like [`Self::fetch_data()`] followed by `convert`, but served from the
+    /// cache if one is configured and holds a fresh response
+    fn fetch_cached<Q: GraphQLQuery, R>(
         &self,
         home_id: &HomeId,
-    ) -> Result<PriceInfo, Box<dyn std::error::Error>> {
+        variables: <Q as GraphQLQuery>::Variables,
+        convert: fn(<Q as GraphQLQuery>::ResponseData) -> Result<R, Error>,
+        expires: impl FnOnce(&R, DateTime<chrono::Utc>) -> DateTime<chrono::Utc>,
+    ) -> Result<R, Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return convert(self.fetch_data::<Q>(variables)?),
+        };
+        let query = Q::build_query(variables);
+        if let Some(result) = cache.load(query.operation_name, home_id, convert) {
+            return Ok(result);
+        }
+        let body = serde_json::to_string(&query)?;
+        let (status, response) = self.transport.execute(&self.authentication, &body)?;
+        check_status(status, &response)?;
+        cache.store(
+            query.operation_name,
+            home_id,
+            &response,
+            self.partial.as_deref(),
+            convert,
+            expires,
+        )
+    }
+
+    /// Run one or more calls in partial data mode.
+    ///
+    /// Normally any GraphQL error entry in a response fails the call. Within
+    /// `f`, responses that carry both data and errors are accepted, and the
+    /// errors are returned alongside the result instead.
+    pub fn partial<R>(
+        &self,
+        f: impl FnOnce(&TibberSession<T>) -> Result<R, Error>,
+    ) -> Result<Partial<R>, Error> {
+        let collector = Arc::new(Mutex::new(Vec::new()));
+        let session = TibberSession {
+            partial: Some(collector.clone()),
+            ..self.clone()
+        };
+        let data = f(&session);
+        let errors = std::mem::take(&mut *collector.lock().unwrap_or_else(|e| e.into_inner()));
+        partial_result(data, errors)
+    }
+
+    /// Get information about the logged in user
+    pub fn get_user(&self) -> Result<User, Error> {
+        let viewer = self.fetch_data::<Viewer>(viewer::Variables {})?;
+        User::new(viewer)
+    }
+
+    /// Get information about a particular home / house
+    pub fn get_home(&self, home_id: &HomeId) -> Result<House, Error> {
+        let id = home_id.0.to_owned();
+        let home = self.fetch_data::<Home>(home::Variables { id })?;
+        Ok(House::new(home))
+    }
+
+    /// Get all subscriptions / power deals, past, present and pending, for
+    /// a particular house / home
+    pub fn get_subscriptions(&self, home_id: &HomeId) -> Result<Vec<Subscription>, Error> {
+        let id = home_id.0.to_owned();
+        let subscriptions = self.fetch_data::<Subscriptions>(subscriptions::Variables { id })?;
+        subscription_list(subscriptions)
+    }
+
+    /// Get Current price information for a particular house / home
+    pub fn get_current_price(&self, home_id: &HomeId) -> Result<PriceInfo, Error> {
         let id = home_id.0.to_owned();
-        let price = fetch_data::<Price>(self.authentication.as_str(), price::Variables { id })?;
-        let price = PriceInfo::new(
-            price
-                .viewer
-                .home
-                .current_subscription
-                .ok_or("No subscription")?
-                .price_info
-                .ok_or("No Price info")?
-                .current
-                .ok_or("No current price")?,
+        self.fetch_cached::<Price, _>(
+            home_id,
+            price::Variables { id },
+            current_price,
+            cache::current_expiry,
         )
-        .ok_or("Could not parse price info")?;
-        Ok(price)
     }
 
     /// Get full day price information for a particular house / home
-    pub fn get_prices_today(
-        &self,
-        home_id: &HomeId,
-    ) -> Result<Vec<PriceInfo>, Box<dyn std::error::Error>> {
+    pub fn get_prices_today(&self, home_id: &HomeId) -> Result<Vec<PriceInfo>, Error> {
         let id = home_id.0.to_owned();
-        let price =
-            fetch_data::<PriceToday>(self.authentication.as_str(), price_today::Variables { id })?;
-        let prices = price
-            .viewer
-            .home
-            .current_subscription
-            .ok_or("No subscription")?
-            .price_info
-            .ok_or("No Price info")?
-            .today;
-        let prices = prices
-            .into_iter()
-            .flatten()
-            .map(PriceInfo::new_t)
-            .flatten()
-            .collect();
-        Ok(prices)
+        self.fetch_cached::<PriceToday, _>(
+            home_id,
+            price_today::Variables { id },
+            prices_today,
+            |prices, now| cache::today_expiry(prices, now),
+        )
     }
 
     /// Get tomorrows prices (if available) for a particular house / home
-    pub fn get_prices_tomorrow(
-        &self,
-        home_id: &HomeId,
-    ) -> Result<Vec<PriceInfo>, Box<dyn std::error::Error>> {
+    pub fn get_prices_tomorrow(&self, home_id: &HomeId) -> Result<Vec<PriceInfo>, Error> {
         let id = home_id.0.to_owned();
-        let price = fetch_data::<PriceTomorrow>(
-            self.authentication.as_str(),
+        self.fetch_cached::<PriceTomorrow, _>(
+            home_id,
             price_tomorrow::Variables { id },
-        )?;
-        let prices = price
-            .viewer
-            .home
-            .current_subscription
-            .ok_or("No subscription")?
-            .price_info
-            .ok_or("No Price info")?
-            .tomorrow;
-        let prices = prices
-            .into_iter()
-            .flatten()
-            .map(PriceInfo::new_f)
-            .flatten()
-            .collect();
-        Ok(prices)
+            prices_tomorrow,
+            |prices, now| cache::tomorrow_expiry(prices, now),
+        )
+    }
+
+    /// Get prices rated against each other for a particular house / home
+    pub fn get_price_rating(&self, home_id: &HomeId) -> Result<PriceRating, Error> {
+        let id = home_id.0.to_owned();
+        let rating = self.fetch_data::<PriceRatings>(price_ratings::Variables { id })?;
+        price_rating(rating)
+    }
+
+    /// Get historical prices for a particular house / home, paging through
+    /// as many requests as needed to cover `span`
+    pub fn get_price_range(
+        &self,
+        home_id: &HomeId,
+        resolution: PriceResolution,
+        span: Span,
+    ) -> Result<Vec<PriceInfo>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables = price_range_variables(home_id, &resolution, page);
+            let (prices, info) = price_range_page(self.fetch_data::<PriceRange>(variables)?)?;
+            pager.add(prices, info);
+        }
+        Ok(pager.finish())
     }
 
     /// Backward compatible typo version of [`Self::get_consumption()`],
@@ -697,7 +1986,7 @@ impl TibberSession {
         home_id: &HomeId,
         resolution: TimeResolution,
         last: u32,
-    ) -> Result<Vec<Consumption>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<Consumption>, Error> {
         self.get_consumption(home_id, resolution, last)
     }
 
@@ -707,34 +1996,10 @@ impl TibberSession {
         home_id: &HomeId,
         resolution: TimeResolution,
         last: u32,
-    ) -> Result<Vec<Consumption>, Box<dyn std::error::Error>> {
-        let id = home_id.0.to_owned();
-        let resolution = match resolution {
-            TimeResolution::Hourly => consumption_history::EnergyResolution::HOURLY,
-            TimeResolution::Daily => consumption_history::EnergyResolution::DAILY,
-            TimeResolution::Weekly => consumption_history::EnergyResolution::WEEKLY,
-            TimeResolution::Monthly => consumption_history::EnergyResolution::MONTHLY,
-            TimeResolution::Annual => consumption_history::EnergyResolution::ANNUAL,
-        };
-        let variables = consumption_history::Variables {
-            id,
-            resolution,
-            num: last.into(),
-        };
-        let history = fetch_data::<ConsumptionHistory>(self.authentication.as_str(), variables)?;
-        let history = history
-            .viewer
-            .home
-            .consumption
-            .ok_or("No History")?
-            .nodes
-            .ok_or("No history nodes")?
-            .into_iter()
-            .flatten()
-            .map(Consumption::new)
-            .flatten()
-            .collect();
-        Ok(history)
+    ) -> Result<Vec<Consumption>, Error> {
+        let variables = consumption_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ConsumptionHistory>(variables)?;
+        consumption_nodes(history, self.lenient)
     }
 
     /// Get historical production data for a particular house / home
@@ -743,33 +2008,118 @@ impl TibberSession {
         home_id: &HomeId,
         resolution: TimeResolution,
         last: u32,
-    ) -> Result<Vec<Production>, Box<dyn std::error::Error>> {
-        let id = home_id.0.to_owned();
-        let resolution = match resolution {
-            TimeResolution::Hourly => production_history::EnergyResolution::HOURLY,
-            TimeResolution::Daily => production_history::EnergyResolution::DAILY,
-            TimeResolution::Weekly => production_history::EnergyResolution::WEEKLY,
-            TimeResolution::Monthly => production_history::EnergyResolution::MONTHLY,
-            TimeResolution::Annual => production_history::EnergyResolution::ANNUAL,
-        };
-        let variables = production_history::Variables {
-            id,
-            resolution,
-            num: last.into(),
-        };
-        let history = fetch_data::<ProductionHistory>(self.authentication.as_str(), variables)?;
-        let history = history
-            .viewer
-            .home
-            .production
-            .ok_or("No History")?
-            .nodes
-            .ok_or("No history nodes")?
-            .into_iter()
-            .flatten()
-            .map(Production::new)
-            .flatten()
-            .collect();
-        Ok(history)
+    ) -> Result<Vec<Production>, Error> {
+        let variables = production_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ProductionHistory>(variables)?;
+        production_nodes(history, self.lenient)
+    }
+
+    /// Get historical consumption data for a particular house / home, with
+    /// the totals calculated by the API. The totals match what is invoiced,
+    /// so prefer these over summing up the nodes.
+    pub fn get_consumption_summary(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<ConsumptionSummary, Error> {
+        let variables = consumption_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ConsumptionHistory>(variables)?;
+        consumption_summary(history, self.lenient)
+    }
+
+    /// Get historical production data for a particular house / home, with
+    /// the totals calculated by the API
+    pub fn get_production_summary(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<ProductionSummary, Error> {
+        let variables = production_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ProductionHistory>(variables)?;
+        production_summary(history, self.lenient)
+    }
+
+    /// Get historical consumption data for a particular house / home within
+    /// `span`, paging through as many requests as needed. With
+    /// `filter_empty_nodes` the API leaves out intervals without data.
+    pub fn get_consumption_range(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        span: Span,
+        filter_empty_nodes: bool,
+    ) -> Result<Vec<Consumption>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables =
+                consumption_range_variables(home_id, &resolution, page, filter_empty_nodes);
+            let (nodes, info) = consumption_range_page(
+                self.fetch_data::<ConsumptionRange>(variables)?,
+                self.lenient,
+            )?;
+            pager.add(nodes, info);
+        }
+        Ok(pager.finish())
+    }
+
+    /// Get historical production data for a particular house / home within
+    /// `span`, paging through as many requests as needed. With
+    /// `filter_empty_nodes` the API leaves out intervals without data.
+    pub fn get_production_range(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        span: Span,
+        filter_empty_nodes: bool,
+    ) -> Result<Vec<Production>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables =
+                production_range_variables(home_id, &resolution, page, filter_empty_nodes);
+            let (nodes, info) = production_range_page(
+                self.fetch_data::<ProductionRange>(variables)?,
+                self.lenient,
+            )?;
+            pager.add(nodes, info);
+        }
+        Ok(pager.finish())
+    }
+
+    /// Submit a manual meter reading for a home without a smart meter.
+    ///
+    /// `reading` is the register value in kWh, read at `time`, which can not
+    /// be in the future.
+    pub fn send_meter_reading(
+        &self,
+        home_id: &HomeId,
+        time: DateTime<FixedOffset>,
+        reading: u32,
+    ) -> Result<MeterReading, Error> {
+        let variables = meter_reading_variables(home_id, time, reading)?;
+        let response = self.fetch_data::<SendMeterReading>(variables)?;
+        MeterReading::new(response)
+    }
+
+    /// Update the profile of a particular home / house, and return the home
+    /// information as it is after the update
+    pub fn update_home(&self, home_id: &HomeId, update: HomeUpdate) -> Result<House, Error> {
+        let variables = update.variables(home_id)?;
+        let response = self.fetch_data::<UpdateHome>(variables)?;
+        Ok(House::updated(response))
+    }
+
+    /// Send a push notification to the Tibber App of the users of the
+    /// account, opening `screen` when tapped
+    pub fn send_push_notification(
+        &self,
+        title: &str,
+        message: &str,
+        screen: AppScreen,
+    ) -> Result<PushNotification, Error> {
+        let variables = push_notification_variables(title, message, screen)?;
+        let response = self.fetch_data::<SendPushNotification>(variables)?;
+        Ok(PushNotification::new(response))
     }
 }
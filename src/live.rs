@@ -0,0 +1,226 @@
+//! Real time measurements over the `graphql-transport-ws` websocket protocol.
+//!
+//! Enabled with the `live` feature. Requires a power meter (Pulse, Watty etc.)
+//! connected to the home, see [`Features::real_time_consumption_enabled`].
+use crate::*;
+use serde_json::json;
+use std::net::TcpStream;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::HeaderValue;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+const SUBSCRIPTION_ID: &str = "1";
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "tibber/tibber.json",
+    query_path = "tibber/live_measurement.graphql",
+    response_derives = "Debug"
+)]
+struct LiveSubscription;
+
+impl LiveMeasurement {
+    fn new(live: live_subscription::LiveSubscriptionLiveMeasurement) -> Result<Self, Error> {
+        let timestamp = parse_time(&live.timestamp)?;
+        Ok(LiveMeasurement {
+            timestamp,
+            power: live.power,
+            last_meter_consumption: live.last_meter_consumption,
+            accumulated_consumption: live.accumulated_consumption,
+            accumulated_production: live.accumulated_production,
+            accumulated_consumption_last_hour: live.accumulated_consumption_last_hour,
+            accumulated_production_last_hour: live.accumulated_production_last_hour,
+            accumulated_cost: live.accumulated_cost,
+            accumulated_reward: live.accumulated_reward,
+            currency: live.currency,
+            min_power: live.min_power,
+            average_power: live.average_power,
+            max_power: live.max_power,
+            power_production: live.power_production,
+            power_reactive: live.power_reactive,
+            power_production_reactive: live.power_production_reactive,
+            min_power_production: live.min_power_production,
+            max_power_production: live.max_power_production,
+            last_meter_production: live.last_meter_production,
+            power_factor: live.power_factor,
+            voltage: [
+                live.voltage_phase1,
+                live.voltage_phase2,
+                live.voltage_phase3,
+            ],
+            current: [live.current_l1, live.current_l2, live.current_l3],
+            signal_strength: live.signal_strength,
+        })
+    }
+}
+
+/// Stream of real time measurements for a single home.
+///
+/// Iterating blocks until the next measurement arrives, and ends when the
+/// server completes the subscription or the connection is closed. An error
+/// from the server also ends it, after the error has been yielded, while a
+/// measurement that can't be decoded is yielded as an error and skipped.
+pub struct LiveStream {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    done: bool,
+}
+
+impl LiveStream {
+    fn connect<T: Transport>(session: &TibberSession<T>, home_id: &HomeId) -> Result<Self, Error> {
+        let mut request = session.websocket_url.as_str().into_client_request()?;
+        let headers = request.headers_mut();
+        headers.insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        headers.insert(
+            "User-Agent",
+            HeaderValue::from_str(&session.user_agent)
+                .map_err(|e| Error::Transport(Box::new(e)))?,
+        );
+        let (socket, _) = tungstenite::connect(request)?;
+        let mut stream = LiveStream {
+            socket,
+            done: false,
+        };
+
+        stream.send(json!({
+            "type": "connection_init",
+            "payload": { "token": session.authentication },
+        }))?;
+        loop {
+            let msg = stream
+                .receive()?
+                .ok_or_else(|| Error::Protocol("connection closed before ack".to_owned()))?;
+            match msg["type"].as_str() {
+                Some("connection_ack") => break,
+                Some("ping") => stream.send(json!({ "type": "pong" }))?,
+                _ => {
+                    return Err(Error::Protocol(format!(
+                        "unexpected message during handshake: {}",
+                        msg
+                    )))
+                }
+            }
+        }
+
+        let query = LiveSubscription::build_query(live_subscription::Variables {
+            id: home_id.0.to_owned(),
+        });
+        stream.send(json!({
+            "id": SUBSCRIPTION_ID,
+            "type": "subscribe",
+            "payload": query,
+        }))?;
+        Ok(stream)
+    }
+
+    fn send(&mut self, msg: serde_json::Value) -> Result<(), Error> {
+        self.socket.send(Message::Text(msg.to_string()))?;
+        Ok(())
+    }
+
+    /// Next protocol message, or None if the connection was closed
+    fn receive(&mut self) -> Result<Option<serde_json::Value>, Error> {
+        loop {
+            match self.socket.read()? {
+                Message::Text(t) => return Ok(Some(serde_json::from_str(&t)?)),
+                Message::Close(_) => return Ok(None),
+                _ => continue,
+            }
+        }
+    }
+
+    fn next_measurement(&mut self) -> Result<Option<LiveMeasurement>, Error> {
+        loop {
+            let msg = match self.receive()? {
+                Some(m) => m,
+                None => return Ok(None),
+            };
+            match msg["type"].as_str() {
+                Some("next") => {
+                    let response: graphql_client::Response<live_subscription::ResponseData> =
+                        serde_json::from_value(msg["payload"].clone())?;
+                    let live = response_data(response, None)?
+                        .live_measurement
+                        .ok_or(Error::MissingField("liveMeasurement"))?;
+                    return Ok(Some(LiveMeasurement::new(live)?));
+                }
+                Some("error") => {
+                    let errors: Vec<graphql_client::Error> =
+                        serde_json::from_value(msg["payload"].clone())?;
+                    return Err(Error::GraphQL(
+                        errors.into_iter().map(GraphQLError::new).collect(),
+                    ));
+                }
+                Some("complete") => return Ok(None),
+                Some("ping") => self.send(json!({ "type": "pong" }))?,
+                _ => continue,
+            }
+        }
+    }
+
+    /// Stop the subscription and close the connection
+    pub fn close(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        if !self.done {
+            self.done = true;
+            self.send(json!({ "id": SUBSCRIPTION_ID, "type": "complete" }))?;
+            self.socket.close(None)?;
+            self.socket.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for LiveStream {
+    type Item = Result<LiveMeasurement, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_measurement() {
+            Ok(Some(m)) => Some(Ok(m)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // A message that could not be decoded is skipped, anything
+                // else ends the subscription
+                let skip = matches!(
+                    e,
+                    Error::Decode(_) | Error::MissingField(_) | Error::Timestamp { .. }
+                );
+                if !skip || !self.socket.can_read() {
+                    let _ = self.shutdown();
+                }
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Drop for LiveStream {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+impl<T: Transport> TibberSession<T> {
+    /// Subscribe to real time measurements for a particular house / home.
+    ///
+    /// Fails if the home has no real time meter enabled.
+    pub fn subscribe_live(&self, home_id: &HomeId) -> Result<LiveStream, Error> {
+        let home = self.get_home(home_id)?;
+        if home.features.real_time_consumption_enabled != Some(true) {
+            return Err(Error::Unsupported("real time consumption not enabled"));
+        }
+        LiveStream::connect(self, home_id)
+    }
+}
@@ -0,0 +1,340 @@
+//! Async version of the bindings, built on the non-blocking reqwest client.
+//!
+//! Enabled with the `async` feature. Requires a tokio runtime.
+use crate::*;
+
+/// An async tibber session, can be shared among tasks. Holds the API token
+/// and the transport that is reused for all calls.
+pub struct AsyncTibberSession<T: AsyncTransport = AsyncReqwestTransport> {
+    pub(crate) authentication: String,
+    pub(crate) transport: Arc<T>,
+    pub(crate) partial: Option<Arc<Mutex<Vec<GraphQLError>>>>,
+    pub(crate) lenient: bool,
+    pub(crate) cache: Option<cache::Cache>,
+}
+
+impl<T: AsyncTransport> Clone for AsyncTibberSession<T> {
+    fn clone(&self) -> Self {
+        AsyncTibberSession {
+            authentication: self.authentication.clone(),
+            transport: self.transport.clone(),
+            partial: self.partial.clone(),
+            lenient: self.lenient,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl AsyncTibberSession {
+    /// Create a new async session object with default settings
+    ///
+    /// # Panics
+    ///
+    /// If the HTTP client cannot be created, see [`Self::builder()`] to
+    /// handle this as an error.
+    pub fn new(authentication: String) -> Self {
+        Self::builder(authentication)
+            .build_async()
+            .expect("Could not create HTTP client")
+    }
+
+    /// Create a session with custom endpoint, user agent, timeouts etc.
+    /// Finish with [`TibberSessionBuilder::build_async()`].
+    pub fn builder(authentication: String) -> TibberSessionBuilder {
+        TibberSessionBuilder::new(authentication)
+    }
+}
+
+impl<T: AsyncTransport> AsyncTibberSession<T> {
+    /// Create a session that sends all requests through `transport`
+    pub fn with_transport(authentication: String, transport: T) -> Self {
+        TibberSessionBuilder::new(authentication).build_async_with_transport(transport)
+    }
+
+    async fn fetch_data<Q: GraphQLQuery>(
+        &self,
+        variables: <Q as GraphQLQuery>::Variables,
+    ) -> Result<<Q as GraphQLQuery>::ResponseData, Error> {
+        let body = serde_json::to_string(&Q::build_query(variables))?;
+        let (status, response) = self.transport.execute(&self.authentication, &body).await?;
+        check_status(status, &response)?;
+        response_data(serde_json::from_str(&response)?, self.partial.as_deref())
+    }
+
+    /// Like `fetch_data` followed by `convert`, but served from the
+    /// cache if one is configured and holds a fresh response
+    async fn fetch_cached<Q: GraphQLQuery, R>(
+        &self,
+        home_id: &HomeId,
+        variables: <Q as GraphQLQuery>::Variables,
+        convert: fn(<Q as GraphQLQuery>::ResponseData) -> Result<R, Error>,
+        expires: impl FnOnce(&R, DateTime<chrono::Utc>) -> DateTime<chrono::Utc>,
+    ) -> Result<R, Error> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return convert(self.fetch_data::<Q>(variables).await?),
+        };
+        // File access goes to the blocking thread pool, to keep it off the
+        // executor
+        let query = Q::build_query(variables);
+        let operation = query.operation_name;
+        let cached = {
+            let (cache, home_id) = (cache.clone(), home_id.clone());
+            tokio::task::spawn_blocking(move || cache.read(operation, &home_id)).await
+        };
+        if let Some(result) = cached
+            .ok()
+            .flatten()
+            .and_then(|r| cache::decode(&r, convert))
+        {
+            return Ok(result);
+        }
+        let body = serde_json::to_string(&query)?;
+        let (status, response) = self.transport.execute(&self.authentication, &body).await?;
+        check_status(status, &response)?;
+        let (result, entry) = cache::entry(&response, self.partial.as_deref(), convert, expires)?;
+        if let Some(entry) = entry {
+            let (cache, home_id) = (cache.clone(), home_id.clone());
+            let _ =
+                tokio::task::spawn_blocking(move || cache.write(operation, &home_id, &entry)).await;
+        }
+        Ok(result)
+    }
+
+    /// Run one or more calls in partial data mode, see [`TibberSession::partial()`].
+    ///
+    /// `f` is handed its own copy of the session, so the returned future can
+    /// own it.
+    pub async fn partial<R, F, Fut>(&self, f: F) -> Result<Partial<R>, Error>
+    where
+        F: FnOnce(AsyncTibberSession<T>) -> Fut,
+        Fut: std::future::Future<Output = Result<R, Error>>,
+    {
+        let collector = Arc::new(Mutex::new(Vec::new()));
+        let session = AsyncTibberSession {
+            partial: Some(collector.clone()),
+            ..self.clone()
+        };
+        let data = f(session).await;
+        let errors = std::mem::take(&mut *collector.lock().unwrap_or_else(|e| e.into_inner()));
+        partial_result(data, errors)
+    }
+
+    /// Get information about the logged in user
+    pub async fn get_user(&self) -> Result<User, Error> {
+        let viewer = self.fetch_data::<Viewer>(viewer::Variables {}).await?;
+        User::new(viewer)
+    }
+
+    /// Get information about a particular home / house
+    pub async fn get_home(&self, home_id: &HomeId) -> Result<House, Error> {
+        let id = home_id.0.to_owned();
+        let home = self.fetch_data::<Home>(home::Variables { id }).await?;
+        Ok(House::new(home))
+    }
+
+    /// Get all subscriptions / power deals, past, present and pending, for
+    /// a particular house / home
+    pub async fn get_subscriptions(&self, home_id: &HomeId) -> Result<Vec<Subscription>, Error> {
+        let id = home_id.0.to_owned();
+        let subscriptions = self
+            .fetch_data::<Subscriptions>(subscriptions::Variables { id })
+            .await?;
+        subscription_list(subscriptions)
+    }
+
+    /// Get Current price information for a particular house / home
+    pub async fn get_current_price(&self, home_id: &HomeId) -> Result<PriceInfo, Error> {
+        let id = home_id.0.to_owned();
+        self.fetch_cached::<Price, _>(
+            home_id,
+            price::Variables { id },
+            current_price,
+            cache::current_expiry,
+        )
+        .await
+    }
+
+    /// Get full day price information for a particular house / home
+    pub async fn get_prices_today(&self, home_id: &HomeId) -> Result<Vec<PriceInfo>, Error> {
+        let id = home_id.0.to_owned();
+        self.fetch_cached::<PriceToday, _>(
+            home_id,
+            price_today::Variables { id },
+            prices_today,
+            |prices, now| cache::today_expiry(prices, now),
+        )
+        .await
+    }
+
+    /// Get tomorrows prices (if available) for a particular house / home
+    pub async fn get_prices_tomorrow(&self, home_id: &HomeId) -> Result<Vec<PriceInfo>, Error> {
+        let id = home_id.0.to_owned();
+        self.fetch_cached::<PriceTomorrow, _>(
+            home_id,
+            price_tomorrow::Variables { id },
+            prices_tomorrow,
+            |prices, now| cache::tomorrow_expiry(prices, now),
+        )
+        .await
+    }
+
+    /// Get prices rated against each other for a particular house / home
+    pub async fn get_price_rating(&self, home_id: &HomeId) -> Result<PriceRating, Error> {
+        let id = home_id.0.to_owned();
+        let rating = self
+            .fetch_data::<PriceRatings>(price_ratings::Variables { id })
+            .await?;
+        price_rating(rating)
+    }
+
+    /// Get historical prices for a particular house / home, paging through
+    /// as many requests as needed to cover `span`
+    pub async fn get_price_range(
+        &self,
+        home_id: &HomeId,
+        resolution: PriceResolution,
+        span: Span,
+    ) -> Result<Vec<PriceInfo>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables = price_range_variables(home_id, &resolution, page);
+            let (prices, info) = price_range_page(self.fetch_data::<PriceRange>(variables).await?)?;
+            pager.add(prices, info);
+        }
+        Ok(pager.finish())
+    }
+
+    /// Get historical consumption data for a particular house / home
+    pub async fn get_consumption(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<Vec<Consumption>, Error> {
+        let variables = consumption_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ConsumptionHistory>(variables).await?;
+        consumption_nodes(history, self.lenient)
+    }
+
+    /// Get historical production data for a particular house / home
+    pub async fn get_production(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<Vec<Production>, Error> {
+        let variables = production_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ProductionHistory>(variables).await?;
+        production_nodes(history, self.lenient)
+    }
+
+    /// Get historical consumption data for a particular house / home, with
+    /// the totals calculated by the API. The totals match what is invoiced,
+    /// so prefer these over summing up the nodes.
+    pub async fn get_consumption_summary(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<ConsumptionSummary, Error> {
+        let variables = consumption_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ConsumptionHistory>(variables).await?;
+        consumption_summary(history, self.lenient)
+    }
+
+    /// Get historical production data for a particular house / home, with
+    /// the totals calculated by the API
+    pub async fn get_production_summary(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        last: u32,
+    ) -> Result<ProductionSummary, Error> {
+        let variables = production_variables(home_id, resolution, last);
+        let history = self.fetch_data::<ProductionHistory>(variables).await?;
+        production_summary(history, self.lenient)
+    }
+
+    /// Get historical consumption data for a particular house / home within
+    /// `span`, paging through as many requests as needed. With
+    /// `filter_empty_nodes` the API leaves out intervals without data.
+    pub async fn get_consumption_range(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        span: Span,
+        filter_empty_nodes: bool,
+    ) -> Result<Vec<Consumption>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables =
+                consumption_range_variables(home_id, &resolution, page, filter_empty_nodes);
+            let (nodes, info) = consumption_range_page(
+                self.fetch_data::<ConsumptionRange>(variables).await?,
+                self.lenient,
+            )?;
+            pager.add(nodes, info);
+        }
+        Ok(pager.finish())
+    }
+
+    /// Get historical production data for a particular house / home within
+    /// `span`, paging through as many requests as needed. With
+    /// `filter_empty_nodes` the API leaves out intervals without data.
+    pub async fn get_production_range(
+        &self,
+        home_id: &HomeId,
+        resolution: TimeResolution,
+        span: Span,
+        filter_empty_nodes: bool,
+    ) -> Result<Vec<Production>, Error> {
+        let mut pager = paging::Pager::new(span);
+        while let Some(page) = pager.next_page() {
+            let variables =
+                production_range_variables(home_id, &resolution, page, filter_empty_nodes);
+            let (nodes, info) = production_range_page(
+                self.fetch_data::<ProductionRange>(variables).await?,
+                self.lenient,
+            )?;
+            pager.add(nodes, info);
+        }
+        Ok(pager.finish())
+    }
+
+    /// Submit a manual meter reading for a home without a smart meter.
+    ///
+    /// `reading` is the register value in kWh, read at `time`, which can not
+    /// be in the future.
+    pub async fn send_meter_reading(
+        &self,
+        home_id: &HomeId,
+        time: DateTime<FixedOffset>,
+        reading: u32,
+    ) -> Result<MeterReading, Error> {
+        let variables = meter_reading_variables(home_id, time, reading)?;
+        let response = self.fetch_data::<SendMeterReading>(variables).await?;
+        MeterReading::new(response)
+    }
+
+    /// Update the profile of a particular home / house, and return the home
+    /// information as it is after the update
+    pub async fn update_home(&self, home_id: &HomeId, update: HomeUpdate) -> Result<House, Error> {
+        let variables = update.variables(home_id)?;
+        let response = self.fetch_data::<UpdateHome>(variables).await?;
+        Ok(House::updated(response))
+    }
+
+    /// Send a push notification to the Tibber App of the users of the
+    /// account, opening `screen` when tapped
+    pub async fn send_push_notification(
+        &self,
+        title: &str,
+        message: &str,
+        screen: AppScreen,
+    ) -> Result<PushNotification, Error> {
+        let variables = push_notification_variables(title, message, screen)?;
+        let response = self.fetch_data::<SendPushNotification>(variables).await?;
+        Ok(PushNotification::new(response))
+    }
+}
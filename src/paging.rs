@@ -0,0 +1,162 @@
+//! Cursor based paging through connections, shared by the blocking and
+//! async sessions
+use crate::*;
+use base64::Engine;
+
+/// Number of nodes asked for in each request
+const PAGE_SIZE: u32 = 200;
+
+/// Items that are paged through, ordered by the start of their interval
+pub(crate) trait Interval {
+    fn start(&self) -> DateTime<FixedOffset>;
+}
+
+impl Interval for PriceInfo {
+    fn start(&self) -> DateTime<FixedOffset> {
+        self.starts_at
+    }
+}
+
+impl Interval for Consumption {
+    fn start(&self) -> DateTime<FixedOffset> {
+        self.from
+    }
+}
+
+impl Interval for Production {
+    fn start(&self) -> DateTime<FixedOffset> {
+        self.from
+    }
+}
+
+/// Arguments selecting a single page of a connection
+pub(crate) struct Page {
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Cursors of a page that was returned
+pub(crate) struct PageInfo {
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+}
+
+/// Cursor pointing just before `time`, so that paging forward from it
+/// includes the interval starting at `time`
+///
+/// The API does not document its cursors. This relies on them being the
+/// base64 encoded start time of a node, as observed in its responses, and
+/// breaks if Tibber changes that format.
+pub(crate) fn cursor_before(time: DateTime<FixedOffset>) -> String {
+    let time = time - chrono::Duration::seconds(1);
+    base64::engine::general_purpose::STANDARD
+        .encode(time.format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string())
+}
+
+/// Keeps track of the pages requested so far for a [`Span`]
+pub(crate) struct Pager<T> {
+    span: Span,
+    items: Vec<T>,
+    cursor: Option<String>,
+    done: bool,
+}
+
+impl<T: Interval> Pager<T> {
+    pub fn new(span: Span) -> Self {
+        let cursor = match &span {
+            Span::Last(_) => None,
+            Span::Since(from) | Span::Between(from, _) => Some(cursor_before(*from)),
+        };
+        let done = matches!(span, Span::Last(0));
+        Pager {
+            span,
+            items: Vec::new(),
+            cursor,
+            done,
+        }
+    }
+
+    /// The next page to request, or None when all items have been collected
+    pub fn next_page(&self) -> Option<Page> {
+        if self.done {
+            return None;
+        }
+        Some(match self.span {
+            Span::Last(n) => Page {
+                first: None,
+                last: Some((n as usize - self.items.len()).min(PAGE_SIZE as usize) as i64),
+                before: self.cursor.clone(),
+                after: None,
+            },
+            Span::Since(_) | Span::Between(_, _) => Page {
+                first: Some(PAGE_SIZE.into()),
+                last: None,
+                before: None,
+                after: self.cursor.clone(),
+            },
+        })
+    }
+
+    /// Add the items of the page returned for the last [`Self::next_page()`]
+    ///
+    /// `items` may have been filtered after decoding, so the end is detected
+    /// from the cursors rather than from an empty page.
+    pub fn add(&mut self, items: Vec<T>, info: PageInfo) {
+        match self.span {
+            Span::Last(n) => {
+                let mut items = items;
+                if let Some(first) = self.items.first() {
+                    let first = first.start();
+                    items.retain(|i| i.start() < first);
+                }
+                items.append(&mut self.items);
+                self.items = items;
+                let stalled = info.start_cursor.is_none() || info.start_cursor == self.cursor;
+                self.cursor = info.start_cursor;
+                self.done = stalled || !info.has_previous_page || self.items.len() >= n as usize;
+            }
+            Span::Since(from) => {
+                let from = self.next_start(from);
+                self.items
+                    .extend(items.into_iter().filter(|i| i.start() >= from));
+                let stalled = info.end_cursor.is_none() || info.end_cursor == self.cursor;
+                self.cursor = info.end_cursor;
+                self.done = stalled || !info.has_next_page;
+            }
+            Span::Between(from, to) => {
+                let reached_end = items.last().is_some_and(|i| i.start() >= to);
+                let from = self.next_start(from);
+                self.items.extend(
+                    items
+                        .into_iter()
+                        .filter(|i| i.start() >= from && i.start() < to),
+                );
+                let stalled = info.end_cursor.is_none() || info.end_cursor == self.cursor;
+                self.cursor = info.end_cursor;
+                self.done = stalled || reached_end || !info.has_next_page;
+            }
+        }
+    }
+
+    /// Earliest start accepted when paging forward, skipping anything
+    /// already collected
+    fn next_start(&self, from: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.items.last() {
+            Some(last) => last.start() + chrono::Duration::seconds(1),
+            None => from,
+        }
+    }
+
+    /// All collected items, oldest first
+    pub fn finish(mut self) -> Vec<T> {
+        if let Span::Last(n) = self.span {
+            let extra = self.items.len().saturating_sub(n as usize);
+            self.items.drain(..extra);
+        }
+        self.items
+    }
+}
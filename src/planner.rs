@@ -0,0 +1,559 @@
+//! Scheduling of loads into the cheapest intervals, based on the prices from
+//! [`TibberSession::get_prices_today()`] and
+//! [`TibberSession::get_prices_tomorrow()`].
+//!
+//! Costs are calculated from [`PriceInfo::total`], and a load is given as a
+//! profile of kWh used in each interval it runs.
+use crate::*;
+use chrono::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A contiguous run of intervals
+pub struct Window {
+    /// Start of the first interval
+    pub starts_at: DateTime<FixedOffset>,
+    /// End of the last interval
+    pub ends_at: DateTime<FixedOffset>,
+    /// Expected cost of running the load profile in the window
+    pub cost: f64,
+    /// The currency of the cost
+    pub currency: String,
+}
+
+/// Length of the intervals, the shortest step between consecutive prices,
+/// or an hour if it can't be told
+pub(crate) fn interval(prices: &[PriceInfo]) -> Duration {
+    prices
+        .windows(2)
+        .map(|w| w[1].starts_at - w[0].starts_at)
+        .filter(|d| *d > Duration::zero())
+        .min()
+        .unwrap_or(Duration::hours(1))
+}
+
+/// Sorted copy of the prices that start at or after `earliest` and end at
+/// or before `deadline`, with the length of each interval
+pub(crate) fn candidates(
+    prices: &[PriceInfo],
+    earliest: DateTime<FixedOffset>,
+    deadline: DateTime<FixedOffset>,
+) -> (Vec<PriceInfo>, Duration) {
+    let mut prices = prices.to_vec();
+    prices.sort_by_key(|p| p.starts_at);
+    prices.dedup_by_key(|p| p.starts_at);
+    let step = interval(&prices);
+    prices.retain(|p| p.starts_at >= earliest && p.starts_at + step <= deadline);
+    (prices, step)
+}
+
+/// Cheapest window to run `profile` in, starting at or after `earliest` and
+/// finishing by `deadline`. Each entry of `profile` is the kWh used in one
+/// interval, so the window is as long as the profile.
+///
+/// Returns None if there is no such window, e.g. when tomorrows prices are
+/// not published yet.
+pub fn cheapest_window(
+    prices: &[PriceInfo],
+    profile: &[f64],
+    earliest: DateTime<FixedOffset>,
+    deadline: DateTime<FixedOffset>,
+) -> Option<Window> {
+    cheapest_windows(prices, profile, earliest, deadline, 1).pop()
+}
+
+/// The `k` cheapest windows to run `profile` in, cheapest first, see
+/// [`cheapest_window()`]. Ties go to the earliest start.
+///
+/// The windows don't overlap, so each is a real alternative rather than the
+/// cheapest window moved by an interval. A window is left out if it overlaps
+/// a cheaper one, so there may be fewer than `k` even when there is room.
+pub fn cheapest_windows(
+    prices: &[PriceInfo],
+    profile: &[f64],
+    earliest: DateTime<FixedOffset>,
+    deadline: DateTime<FixedOffset>,
+    k: usize,
+) -> Vec<Window> {
+    if profile.is_empty() {
+        return Vec::new();
+    }
+    let (prices, step) = candidates(prices, earliest, deadline);
+    let mut windows: Vec<Window> = prices
+        .windows(profile.len())
+        .filter(|run| {
+            run.windows(2)
+                .all(|w| w[1].starts_at - w[0].starts_at == step)
+        })
+        .map(|run| Window {
+            starts_at: run[0].starts_at,
+            ends_at: run[run.len() - 1].starts_at + step,
+            cost: run.iter().zip(profile).map(|(p, kwh)| p.total * kwh).sum(),
+            currency: run[0].currency.clone(),
+        })
+        .collect();
+    windows.sort_by(|a, b| a.cost.total_cmp(&b.cost));
+    let mut chosen: Vec<Window> = Vec::new();
+    for w in windows {
+        if chosen.len() == k {
+            break;
+        }
+        if chosen
+            .iter()
+            .all(|c| w.ends_at <= c.starts_at || c.ends_at <= w.starts_at)
+        {
+            chosen.push(w);
+        }
+    }
+    chosen
+}
+
+#[derive(Debug, Clone)]
+/// What a load that can be split into several runs needs, for
+/// [`cheapest_schedule()`]. Lengths are counted in intervals.
+pub struct Requirements {
+    intervals: usize,
+    min_on: usize,
+    min_off: usize,
+    max_starts: Option<usize>,
+}
+
+impl Requirements {
+    /// Run for `intervals` intervals in total, in any number of runs
+    pub fn new(intervals: usize) -> Self {
+        Requirements {
+            intervals,
+            min_on: 1,
+            min_off: 0,
+            max_starts: None,
+        }
+    }
+
+    /// Each run must last at least `intervals`
+    pub fn min_on(mut self, intervals: usize) -> Self {
+        self.min_on = intervals.max(1);
+        self
+    }
+
+    /// There must be at least `intervals` between runs
+    pub fn min_off(mut self, intervals: usize) -> Self {
+        self.min_off = intervals;
+        self
+    }
+
+    /// Start at most `starts` times
+    pub fn max_starts(mut self, starts: usize) -> Self {
+        self.max_starts = Some(starts);
+        self
+    }
+}
+
+/// Whether a load is running, and for how many intervals
+enum Mode {
+    On(usize),
+    Off(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single run of a [`Schedule`]
+pub struct Block {
+    /// When to start
+    pub starts_at: DateTime<FixedOffset>,
+    /// How long to run
+    pub duration: Duration,
+    /// Expected cost of the run
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// When to run a load, see [`cheapest_schedule()`]
+pub struct Schedule {
+    /// The runs, in order
+    pub blocks: Vec<Block>,
+    /// Expected cost of all runs
+    pub cost: f64,
+    /// The currency of the cost
+    pub currency: String,
+}
+
+/// Cheapest set of intervals to run a load using `kwh` in each interval,
+/// starting at or after `earliest` and finishing by `deadline`, within the
+/// limits of `requirements`.
+///
+/// Returns None if the requirements can't be met before the deadline.
+///
+/// With `n` intervals to choose from, this takes time and memory in the
+/// order of `n * intervals * (min_on + min_off + 1) * starts`, where
+/// `starts` is 1 without a limit on starts, and otherwise
+/// `min(max_starts, intervals / min_on) + 1`.
+pub fn cheapest_schedule(
+    prices: &[PriceInfo],
+    kwh: f64,
+    requirements: &Requirements,
+    earliest: DateTime<FixedOffset>,
+    deadline: DateTime<FixedOffset>,
+) -> Option<Schedule> {
+    let (prices, step) = candidates(prices, earliest, deadline);
+    let need = requirements.intervals;
+    let min_on = requirements.min_on;
+    let min_off = requirements.min_off;
+    if need == 0 || prices.len() < need {
+        return None;
+    }
+
+    // A state is the number of intervals run so far, the number of starts
+    // so far (only tracked with a limit), and the mode: on for r intervals
+    // (capped at min_on), or off for g intervals (capped at min_off).
+    // Every run lasts at least min_on, so there can't be more than
+    // need / min_on starts.
+    let starts = match requirements.max_starts {
+        Some(m) => m.min(need / min_on) + 1,
+        None => 1,
+    };
+    let modes = min_on + min_off + 1;
+    let on = |r: usize| r.min(min_on) - 1;
+    let off = |g: usize| min_on + g.min(min_off);
+    let index = |u: usize, s: usize, m: usize| (u * starts + s) * modes + m;
+    let states = (need + 1) * starts * modes;
+
+    let mut cost = vec![f64::INFINITY; states];
+    cost[index(0, 0, off(min_off))] = 0.0;
+    let mut parents: Vec<Vec<u32>> = Vec::with_capacity(prices.len());
+    for (i, price) in prices.iter().enumerate() {
+        let gap = i > 0 && price.starts_at - prices[i - 1].starts_at != step;
+        let mut next = vec![f64::INFINITY; states];
+        let mut parent = vec![u32::MAX; states];
+        let mut relax = |to: usize, from: usize, c: f64| {
+            if c < next[to] {
+                next[to] = c;
+                parent[to] = from as u32;
+            }
+        };
+        for u in 0..=need {
+            for s in 0..starts {
+                for m in 0..modes {
+                    let from = index(u, s, m);
+                    let c = cost[from];
+                    if c.is_infinite() {
+                        continue;
+                    }
+                    // A gap in the prices ends a run, and counts as time off
+                    let mode = match (m < min_on, gap) {
+                        (true, true) if m + 1 < min_on => continue,
+                        (_, true) => Mode::Off(min_off),
+                        (true, false) => Mode::On(m + 1),
+                        (false, false) => Mode::Off(m - min_on),
+                    };
+                    let cost_on = c + price.total * kwh;
+                    match mode {
+                        Mode::On(r) => {
+                            if u < need {
+                                relax(index(u + 1, s, on(r + 1)), from, cost_on);
+                            }
+                            if r >= min_on {
+                                relax(index(u, s, off(1)), from, c);
+                            }
+                        }
+                        Mode::Off(g) => {
+                            relax(index(u, s, off(g + 1)), from, c);
+                            if g < min_off || u == need {
+                                continue;
+                            }
+                            let s = match requirements.max_starts {
+                                Some(_) if s + 1 < starts => s + 1,
+                                Some(_) => continue,
+                                None => 0,
+                            };
+                            relax(index(u + 1, s, on(1)), from, cost_on);
+                        }
+                    }
+                }
+            }
+        }
+        cost = next;
+        parents.push(parent);
+    }
+
+    // Cheapest finished state, where the last run is long enough
+    let (mut state, total) = (0..starts)
+        .flat_map(|s| (0..modes).map(move |m| (s, m)))
+        .filter(|(_, m)| *m >= min_on - 1)
+        .map(|(s, m)| index(need, s, m))
+        .map(|i| (i, cost[i]))
+        .filter(|(_, c)| c.is_finite())
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    let mut running = vec![false; prices.len()];
+    for i in (0..prices.len()).rev() {
+        let from = parents[i][state] as usize;
+        running[i] = from / (starts * modes) != state / (starts * modes);
+        state = from;
+    }
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for (i, price) in prices.iter().enumerate() {
+        if !running[i] {
+            continue;
+        }
+        let cost = price.total * kwh;
+        match blocks.last_mut() {
+            Some(b) if i > 0 && running[i - 1] && b.starts_at + b.duration == price.starts_at => {
+                b.duration += step;
+                b.cost += cost;
+            }
+            _ => blocks.push(Block {
+                starts_at: price.starts_at,
+                duration: step,
+                cost,
+            }),
+        }
+    }
+    Some(Schedule {
+        blocks,
+        cost: total,
+        currency: prices[0].currency.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(hours: &[(i64, f64)]) -> Vec<PriceInfo> {
+        let start = DateTime::parse_from_rfc3339("2024-03-01T00:00:00+01:00").unwrap();
+        hours
+            .iter()
+            .map(|&(h, total)| PriceInfo {
+                total,
+                energy: total,
+                tax: 0.0,
+                starts_at: start + Duration::hours(h),
+                currency: "NOK".into(),
+                level: PriceLevel::Normal,
+            })
+            .collect()
+    }
+
+    fn bounds(prices: &[PriceInfo]) -> (DateTime<FixedOffset>, DateTime<FixedOffset>) {
+        let first = prices[0].starts_at;
+        (first, first + Duration::days(2))
+    }
+
+    /// Cheapest cost by trying every set of intervals
+    fn brute_force(
+        prices: &[PriceInfo],
+        kwh: f64,
+        need: usize,
+        req: (usize, usize, Option<usize>),
+    ) -> Option<f64> {
+        let (min_on, min_off, max_starts) = req;
+        let n = prices.len();
+        let step = interval(prices);
+        let joined = |i: usize| i > 0 && prices[i].starts_at - prices[i - 1].starts_at == step;
+        let mut best: Option<f64> = None;
+        for mask in 0u32..(1 << n) {
+            if mask.count_ones() as usize != need {
+                continue;
+            }
+            let on = |i: usize| mask & (1 << i) != 0;
+            // Runs as (first, last) index
+            let mut runs: Vec<(usize, usize)> = Vec::new();
+            for i in (0..n).filter(|&i| on(i)) {
+                match runs.last_mut() {
+                    Some(run) if run.1 + 1 == i && joined(i) => run.1 = i,
+                    _ => runs.push((i, i)),
+                }
+            }
+            let long_enough = runs.iter().all(|r| r.1 - r.0 + 1 >= min_on.max(1));
+            let apart = runs.windows(2).all(|w| {
+                let gap = (w[0].1 + 1..=w[1].0).any(|i| !joined(i));
+                let off = w[1].0 - w[0].1 - 1;
+                gap || off >= min_off
+            });
+            let starts = max_starts.is_none_or(|m| runs.len() <= m);
+            if long_enough && apart && starts {
+                let cost: f64 = (0..n)
+                    .filter(|&i| on(i))
+                    .map(|i| prices[i].total * kwh)
+                    .sum();
+                if best.is_none_or(|b| cost < b) {
+                    best = Some(cost);
+                }
+            }
+        }
+        best
+    }
+
+    /// Small xorshift generator, to avoid a dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn starts(windows: &[Window]) -> Vec<DateTime<FixedOffset>> {
+        windows.iter().map(|w| w.starts_at).collect()
+    }
+
+    #[test]
+    fn windows_do_not_span_gaps() {
+        // Hour 2 is missing, so the cheap hours 1 and 3 are not a window
+        let prices = prices(&[(0, 5.0), (1, 1.0), (3, 1.0), (4, 5.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let windows = cheapest_windows(&prices, &[1.0, 1.0], earliest, deadline, 5);
+        assert_eq!(starts(&windows), [prices[0].starts_at, prices[2].starts_at]);
+        assert!(windows.iter().all(|w| w.cost == 6.0));
+    }
+
+    #[test]
+    fn window_within_bounds() {
+        let prices = prices(&[(0, 1.0), (1, 5.0), (2, 4.0), (3, 3.0), (4, 5.0), (5, 1.0)]);
+        let earliest = prices[1].starts_at;
+        let deadline = prices[5].starts_at;
+        let window = cheapest_window(&prices, &[1.0], earliest, deadline).unwrap();
+        assert_eq!(window.starts_at, prices[3].starts_at);
+        // A window may start right at earliest and end right at the deadline
+        let window = cheapest_window(&prices, &[1.0; 4], earliest, deadline).unwrap();
+        assert_eq!((window.starts_at, window.ends_at), (earliest, deadline));
+        assert_eq!(window.cost, 17.0);
+        // No room at all
+        assert_eq!(
+            cheapest_window(&prices, &[1.0; 5], earliest, deadline),
+            None
+        );
+    }
+
+    #[test]
+    fn uneven_profile() {
+        let prices = prices(&[(0, 5.0), (1, 1.0), (2, 9.0), (3, 1.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let heavy_first = cheapest_window(&prices, &[3.0, 1.0], earliest, deadline).unwrap();
+        assert_eq!(
+            (heavy_first.starts_at, heavy_first.cost),
+            (prices[1].starts_at, 12.0)
+        );
+        let heavy_last = cheapest_window(&prices, &[1.0, 3.0], earliest, deadline).unwrap();
+        assert_eq!(
+            (heavy_last.starts_at, heavy_last.cost),
+            (prices[0].starts_at, 8.0)
+        );
+        assert_eq!(heavy_last.ends_at, prices[2].starts_at);
+    }
+
+    #[test]
+    fn empty_profile() {
+        let prices = prices(&[(0, 1.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        assert_eq!(cheapest_window(&prices, &[], earliest, deadline), None);
+        assert!(cheapest_windows(&prices, &[], earliest, deadline, 3).is_empty());
+    }
+
+    #[test]
+    fn fewer_windows_than_asked_for() {
+        let prices = prices(&[(0, 3.0), (1, 1.0), (2, 2.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let windows = cheapest_windows(&prices, &[1.0], earliest, deadline, 10);
+        let costs: Vec<f64> = windows.iter().map(|w| w.cost).collect();
+        assert_eq!(costs, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn windows_do_not_overlap() {
+        let prices = prices(&[(0, 1.0), (1, 1.0), (2, 1.0), (3, 5.0), (4, 5.0), (5, 1.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let windows = cheapest_windows(&prices, &[1.0, 1.0], earliest, deadline, 3);
+        let expected = [
+            prices[0].starts_at,
+            prices[2].starts_at,
+            prices[4].starts_at,
+        ];
+        assert_eq!(starts(&windows), expected);
+        let costs: Vec<f64> = windows.iter().map(|w| w.cost).collect();
+        assert_eq!(costs, [2.0, 6.0, 6.0]);
+    }
+
+    #[test]
+    fn schedule_matches_brute_force() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let n = 1 + rng.below(10) as usize;
+            let mut hour = 0;
+            let hours: Vec<(i64, f64)> = (0..n)
+                .map(|_| {
+                    // Now and then leave out an hour or two
+                    hour += 1 + (rng.below(6) / 5) as i64 * (1 + rng.below(2) as i64);
+                    (hour, rng.below(20) as f64 / 4.0)
+                })
+                .collect();
+            let prices = prices(&hours);
+            let need = 1 + rng.below(n as u64) as usize;
+            let min_on = rng.below(4) as usize;
+            let min_off = rng.below(4) as usize;
+            let max_starts = match rng.below(2) {
+                0 => None,
+                _ => Some(rng.below(4) as usize),
+            };
+
+            let mut req = Requirements::new(need).min_on(min_on).min_off(min_off);
+            if let Some(m) = max_starts {
+                req = req.max_starts(m);
+            }
+            let (earliest, deadline) = bounds(&prices);
+            let schedule = cheapest_schedule(&prices, 1.5, &req, earliest, deadline);
+            let expected = brute_force(&prices, 1.5, need, (min_on, min_off, max_starts));
+            let case = format!(
+                "{:?} need {} on {} off {} starts {:?}",
+                hours, need, min_on, min_off, max_starts
+            );
+            match (schedule, expected) {
+                (Some(s), Some(e)) => {
+                    assert!(
+                        (s.cost - e).abs() < 1e-9,
+                        "{} cost {} expected {}",
+                        case,
+                        s.cost,
+                        e
+                    );
+                    let blocks: f64 = s.blocks.iter().map(|b| b.cost).sum();
+                    assert!((blocks - s.cost).abs() < 1e-9, "{}", case);
+                    let run: Duration = s.blocks.iter().map(|b| b.duration).sum();
+                    assert_eq!(run, interval(&prices) * need as i32, "{}", case);
+                }
+                (None, None) => {}
+                (s, e) => panic!("{} got {:?} expected {:?}", case, s.map(|s| s.cost), e),
+            }
+        }
+    }
+
+    #[test]
+    fn runs_do_not_span_gaps() {
+        // Hour 2 is missing, so 1 and 3 are not one run
+        let prices = prices(&[(0, 9.0), (1, 1.0), (3, 1.0), (4, 9.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let req = Requirements::new(2).min_on(2);
+        let schedule = cheapest_schedule(&prices, 1.0, &req, earliest, deadline).unwrap();
+        assert_eq!(schedule.cost, 10.0);
+        assert_eq!(schedule.blocks.len(), 1);
+
+        let req = Requirements::new(2);
+        let schedule = cheapest_schedule(&prices, 1.0, &req, earliest, deadline).unwrap();
+        assert_eq!(schedule.cost, 2.0);
+        assert_eq!(schedule.blocks.len(), 2);
+        assert_eq!(schedule.blocks[1].starts_at, prices[2].starts_at);
+    }
+
+    #[test]
+    fn no_starts_allowed() {
+        let prices = prices(&[(0, 1.0), (1, 2.0)]);
+        let (earliest, deadline) = bounds(&prices);
+        let req = Requirements::new(1).max_starts(0);
+        assert_eq!(
+            cheapest_schedule(&prices, 1.0, &req, earliest, deadline),
+            None
+        );
+    }
+}
@@ -0,0 +1,566 @@
+//! Persistent time series of consumption, production and prices, kept in
+//! an SQLite database.
+//!
+//! Enabled with the `store` feature. [`Store::sync()`] only fetches what is
+//! missing since the last sync, and rows are keyed on the start of their
+//! interval, so syncing twice does not duplicate anything.
+use crate::*;
+use chrono::{Duration, DurationRound, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+
+/// How far back hours stored without cost are fetched again. Older hours
+/// are given up on, so they don't hold back every later sync.
+const RETRY_WINDOW: Duration = Duration::days(7);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS consumption (
+    home_id TEXT NOT NULL,
+    from_ts INTEGER NOT NULL,
+    from_time TEXT NOT NULL,
+    to_time TEXT NOT NULL,
+    cost REAL,
+    total_cost REAL,
+    unit_cost REAL,
+    unit_price REAL,
+    unit_price_vat REAL,
+    currency TEXT,
+    kwh REAL,
+    PRIMARY KEY (home_id, from_ts)
+);
+CREATE TABLE IF NOT EXISTS production (
+    home_id TEXT NOT NULL,
+    from_ts INTEGER NOT NULL,
+    from_time TEXT NOT NULL,
+    to_time TEXT NOT NULL,
+    profit REAL,
+    unit_price REAL,
+    unit_price_vat REAL,
+    currency TEXT,
+    kwh REAL,
+    PRIMARY KEY (home_id, from_ts)
+);
+CREATE TABLE IF NOT EXISTS prices (
+    home_id TEXT NOT NULL,
+    starts_ts INTEGER NOT NULL,
+    starts_at TEXT NOT NULL,
+    total REAL NOT NULL,
+    energy REAL NOT NULL,
+    tax REAL NOT NULL,
+    currency TEXT NOT NULL,
+    level TEXT,
+    PRIMARY KEY (home_id, starts_ts)
+);
+";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Number of rows written by [`Store::sync()`]
+pub struct SyncReport {
+    /// Hours of consumption added or updated
+    pub consumption: usize,
+    /// Hours of production added or updated
+    pub production: usize,
+    /// Prices added or updated
+    pub prices: usize,
+}
+
+/// Hourly consumption, production and prices for any number of homes
+pub struct Store {
+    conn: Connection,
+    backfill: Duration,
+}
+
+impl Store {
+    /// Open or create the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Create a database that only lives as long as the store
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Store {
+            conn,
+            backfill: Duration::days(365),
+        })
+    }
+
+    /// How far back to fetch history for a home that has not been synced
+    /// before, one year by default
+    pub fn backfill(mut self, backfill: Duration) -> Self {
+        self.backfill = backfill;
+        self
+    }
+
+    /// Fetch the hours missing for `home_id` since the last sync, and
+    /// today's and tomorrow's prices.
+    ///
+    /// Hours stored without cost in the last week, e.g. from a lenient
+    /// session, are fetched again until the cost is known.
+    pub fn sync<T: Transport>(
+        &mut self,
+        session: &TibberSession<T>,
+        home_id: &HomeId,
+    ) -> Result<SyncReport, Error> {
+        let home = home_id.0.as_str();
+        let now = Utc::now().fixed_offset();
+        let default_start = now.duration_trunc(Duration::hours(1)).unwrap_or(now) - self.backfill;
+        let mut report = SyncReport::default();
+
+        let start = self.resume("consumption", "cost", home, now - RETRY_WINDOW)?;
+        let consumption = session.get_consumption_range(
+            home_id,
+            TimeResolution::Hourly,
+            Span::Since(start.unwrap_or(default_start)),
+            true,
+        )?;
+        report.consumption = self.insert_consumption(home_id, &consumption)?;
+
+        let start = self.resume("production", "profit", home, now - RETRY_WINDOW)?;
+        let production = session.get_production_range(
+            home_id,
+            TimeResolution::Hourly,
+            Span::Since(start.unwrap_or(default_start)),
+            true,
+        )?;
+        report.production = self.insert_production(home_id, &production)?;
+
+        let start = self.latest_price(home)?;
+        let mut prices = session.get_price_range(
+            home_id,
+            PriceResolution::Hourly,
+            Span::Since(start.unwrap_or(default_start)),
+        )?;
+        prices.extend(session.get_prices_today(home_id)?);
+        prices.extend(session.get_prices_tomorrow(home_id)?);
+        report.prices = self.insert_prices(home_id, &prices)?;
+        Ok(report)
+    }
+
+    /// Where to continue syncing `table`: the first hour without `cost`
+    /// since `retry_since`, or else the last hour stored
+    fn resume(
+        &self,
+        table: &str,
+        cost: &str,
+        home: &str,
+        retry_since: DateTime<FixedOffset>,
+    ) -> Result<Option<DateTime<FixedOffset>>, Error> {
+        let incomplete = format!(
+            "SELECT from_time FROM {} WHERE home_id = ?1 AND {} IS NULL AND from_ts >= ?2 \
+             ORDER BY from_ts LIMIT 1",
+            table, cost
+        );
+        let latest = format!(
+            "SELECT from_time FROM {} WHERE home_id = ?1 ORDER BY from_ts DESC LIMIT 1",
+            table
+        );
+        let time: Option<String> = match self
+            .conn
+            .query_row(&incomplete, params![home, retry_since.timestamp()], |row| {
+                row.get(0)
+            })
+            .optional()?
+        {
+            Some(t) => Some(t),
+            None => self
+                .conn
+                .query_row(&latest, [home], |row| row.get(0))
+                .optional()?,
+        };
+        time.map(|t| parse_time(&t)).transpose()
+    }
+
+    fn latest_price(&self, home: &str) -> Result<Option<DateTime<FixedOffset>>, Error> {
+        let time: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT starts_at FROM prices WHERE home_id = ?1 ORDER BY starts_ts DESC LIMIT 1",
+                [home],
+                |row| row.get(0),
+            )
+            .optional()?;
+        time.map(|t| parse_time(&t)).transpose()
+    }
+
+    /// Add or replace consumption for `home_id`, returns the number of rows written
+    pub fn insert_consumption(
+        &mut self,
+        home_id: &HomeId,
+        consumption: &[Consumption],
+    ) -> Result<usize, Error> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO consumption (home_id, from_ts, from_time, to_time, cost, \
+                 total_cost, unit_cost, unit_price, unit_price_vat, currency, kwh) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for c in consumption {
+                stmt.execute(params![
+                    home_id.0,
+                    c.from.timestamp(),
+                    c.from.to_rfc3339(),
+                    c.to.to_rfc3339(),
+                    c.cost,
+                    c.total_cost,
+                    c.unit_cost,
+                    c.unit_price,
+                    c.unit_price_vat,
+                    c.currency,
+                    kwh(&c.energy),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(consumption.len())
+    }
+
+    /// Add or replace production for `home_id`, returns the number of rows written
+    pub fn insert_production(
+        &mut self,
+        home_id: &HomeId,
+        production: &[Production],
+    ) -> Result<usize, Error> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO production (home_id, from_ts, from_time, to_time, profit, \
+                 unit_price, unit_price_vat, currency, kwh) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for p in production {
+                stmt.execute(params![
+                    home_id.0,
+                    p.from.timestamp(),
+                    p.from.to_rfc3339(),
+                    p.to.to_rfc3339(),
+                    p.profit,
+                    p.unit_price,
+                    p.unit_price_vat,
+                    p.currency,
+                    kwh(&p.energy),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(production.len())
+    }
+
+    /// Add or replace prices for `home_id`, returns the number of rows written
+    pub fn insert_prices(
+        &mut self,
+        home_id: &HomeId,
+        prices: &[PriceInfo],
+    ) -> Result<usize, Error> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO prices (home_id, starts_ts, starts_at, total, energy, tax, \
+                 currency, level) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for p in prices {
+                stmt.execute(params![
+                    home_id.0,
+                    p.starts_at.timestamp(),
+                    p.starts_at.to_rfc3339(),
+                    p.total,
+                    p.energy,
+                    p.tax,
+                    p.currency,
+                    level_name(&p.level),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(prices.len())
+    }
+
+    /// Stored consumption for `home_id` starting in `from..to`, oldest first
+    pub fn consumption(
+        &self,
+        home_id: &HomeId,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+    ) -> Result<Vec<Consumption>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT from_time, to_time, cost, total_cost, unit_cost, unit_price, unit_price_vat, \
+             currency, kwh FROM consumption \
+             WHERE home_id = ?1 AND from_ts >= ?2 AND from_ts < ?3 ORDER BY from_ts",
+        )?;
+        let rows = stmt.query_map(
+            params![home_id.0, from.timestamp(), to.timestamp()],
+            |row| {
+                Ok(Consumption {
+                    from: time(row, 0)?,
+                    to: time(row, 1)?,
+                    cost: row.get(2)?,
+                    total_cost: row.get(3)?,
+                    unit_cost: row.get(4)?,
+                    unit_price: row.get(5)?,
+                    unit_price_vat: row.get(6)?,
+                    currency: row.get(7)?,
+                    energy: energy(row.get(8)?),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Stored production for `home_id` starting in `from..to`, oldest first
+    pub fn production(
+        &self,
+        home_id: &HomeId,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+    ) -> Result<Vec<Production>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT from_time, to_time, profit, unit_price, unit_price_vat, currency, kwh \
+             FROM production \
+             WHERE home_id = ?1 AND from_ts >= ?2 AND from_ts < ?3 ORDER BY from_ts",
+        )?;
+        let rows = stmt.query_map(
+            params![home_id.0, from.timestamp(), to.timestamp()],
+            |row| {
+                Ok(Production {
+                    from: time(row, 0)?,
+                    to: time(row, 1)?,
+                    profit: row.get(2)?,
+                    unit_price: row.get(3)?,
+                    unit_price_vat: row.get(4)?,
+                    currency: row.get(5)?,
+                    energy: energy(row.get(6)?),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Stored prices for `home_id` starting in `from..to`, oldest first
+    pub fn prices(
+        &self,
+        home_id: &HomeId,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+    ) -> Result<Vec<PriceInfo>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT starts_at, total, energy, tax, currency, level FROM prices \
+             WHERE home_id = ?1 AND starts_ts >= ?2 AND starts_ts < ?3 ORDER BY starts_ts",
+        )?;
+        let rows = stmt.query_map(
+            params![home_id.0, from.timestamp(), to.timestamp()],
+            |row| {
+                Ok(PriceInfo {
+                    starts_at: time(row, 0)?,
+                    total: row.get(1)?,
+                    energy: row.get(2)?,
+                    tax: row.get(3)?,
+                    currency: row.get(4)?,
+                    level: level_from_name(row.get(5)?),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+/// Timestamp stored as RFC 3339 text in column `idx`
+fn time(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<DateTime<FixedOffset>> {
+    let text: String = row.get(idx)?;
+    DateTime::parse_from_rfc3339(&text).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn kwh(energy: &EnergyUnits) -> Option<f64> {
+    match energy {
+        EnergyUnits::kWh(e) => Some(*e),
+        EnergyUnits::None => None,
+    }
+}
+
+fn energy(kwh: Option<f64>) -> EnergyUnits {
+    match kwh {
+        Some(e) => EnergyUnits::kWh(e),
+        None => EnergyUnits::None,
+    }
+}
+
+fn level_name(level: &PriceLevel) -> Option<&str> {
+    match level {
+        PriceLevel::VeryCheap => Some("VERY_CHEAP"),
+        PriceLevel::Cheap => Some("CHEAP"),
+        PriceLevel::Normal => Some("NORMAL"),
+        PriceLevel::Expensive => Some("EXPENSIVE"),
+        PriceLevel::VeryExpensive => Some("VERY_EXPENSIVE"),
+        PriceLevel::Other(s) => Some(s.as_str()),
+        PriceLevel::None => None,
+    }
+}
+
+fn level_from_name(name: Option<String>) -> PriceLevel {
+    match name.as_deref() {
+        Some("VERY_CHEAP") => PriceLevel::VeryCheap,
+        Some("CHEAP") => PriceLevel::Cheap,
+        Some("NORMAL") => PriceLevel::Normal,
+        Some("EXPENSIVE") => PriceLevel::Expensive,
+        Some("VERY_EXPENSIVE") => PriceLevel::VeryExpensive,
+        Some(_) => PriceLevel::Other(name.unwrap_or_default()),
+        None => PriceLevel::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hour(from: DateTime<FixedOffset>, cost: Option<f64>) -> Consumption {
+        Consumption {
+            from,
+            to: from + Duration::hours(1),
+            cost,
+            total_cost: cost,
+            unit_cost: None,
+            unit_price: cost,
+            unit_price_vat: None,
+            currency: Some("NOK".into()),
+            energy: EnergyUnits::kWh(1.0),
+        }
+    }
+
+    #[test]
+    fn resume_skips_old_hours_without_cost() {
+        let mut store = Store::open_in_memory().unwrap();
+        let home = HomeId("home".into());
+        let now = Utc::now()
+            .fixed_offset()
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        let old = now - Duration::days(30);
+        let recent = now - Duration::days(2);
+        let latest = now - Duration::hours(1);
+
+        store
+            .insert_consumption(&home, &[hour(old, None), hour(latest, Some(1.0))])
+            .unwrap();
+        let start = store.resume("consumption", "cost", "home", now - RETRY_WINDOW);
+        assert_eq!(start.unwrap(), Some(latest));
+
+        store
+            .insert_consumption(&home, &[hour(recent, None)])
+            .unwrap();
+        let start = store.resume("consumption", "cost", "home", now - RETRY_WINDOW);
+        assert_eq!(start.unwrap(), Some(recent));
+    }
+
+    /// A single page of hourly consumption starting at each of `hours`
+    fn consumption_page(hours: &[(DateTime<FixedOffset>, Option<f64>)]) -> String {
+        let edges: Vec<String> = hours
+            .iter()
+            .map(|(from, cost)| {
+                format!(
+                    r#"{{"node":{{"from":"{}","to":"{}","cost":{},"totalCost":null,"unitCost":null,"unitPrice":1.0,"unitPriceVAT":0.2,"consumption":1.0,"consumptionUnit":"kWh","currency":"NOK"}}}}"#,
+                    from.to_rfc3339(),
+                    (*from + Duration::hours(1)).to_rfc3339(),
+                    cost.map_or("null".to_string(), |c| c.to_string())
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"data":{{"viewer":{{"home":{{"consumption":{{"pageInfo":{{"startCursor":"s","endCursor":"e","hasNextPage":false,"hasPreviousPage":true}},"edges":[{}]}}}}}}}}}}"#,
+            edges.join(",")
+        )
+    }
+
+    fn after(request: &str) -> String {
+        let request: serde_json::Value = serde_json::from_str(request).unwrap();
+        request["variables"]["after"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn sync_fetches_only_what_is_missing() {
+        let home = HomeId("home".into());
+        let now = Utc::now()
+            .fixed_offset()
+            .duration_trunc(Duration::hours(1))
+            .unwrap();
+        let h = |n: i64| now - Duration::hours(n);
+        const NO_PAGE: &str = r#""pageInfo":{"startCursor":null,"endCursor":null,"hasNextPage":false,"hasPreviousPage":false}"#;
+        let production = format!(
+            r#"{{"data":{{"viewer":{{"home":{{"production":{{{},"edges":[]}}}}}}}}}}"#,
+            NO_PAGE
+        );
+        let price_info = |prices: String| {
+            format!(
+                r#"{{"data":{{"viewer":{{"home":{{"currentSubscription":{{"priceInfo":{{{}}}}}}}}}}}}}"#,
+                prices
+            )
+        };
+        let transport = Arc::new(
+            FixtureTransport::new()
+                // The cost of the third hour is not known at the first sync
+                .with_response(
+                    "ConsumptionRange",
+                    consumption_page(&[
+                        (h(6), Some(1.0)),
+                        (h(5), Some(1.0)),
+                        (h(4), None),
+                        (h(3), Some(1.0)),
+                        (h(2), Some(1.0)),
+                    ]),
+                )
+                .with_response(
+                    "ConsumptionRange",
+                    consumption_page(&[
+                        (h(4), Some(2.0)),
+                        (h(3), Some(1.0)),
+                        (h(2), Some(1.0)),
+                        (h(1), Some(1.0)),
+                    ]),
+                )
+                .with_response("ProductionRange", production)
+                .with_response(
+                    "PriceRange",
+                    price_info(format!(r#""range":{{{},"nodes":[]}}"#, NO_PAGE)),
+                )
+                .with_response("PriceToday", price_info(r#""today":[]"#.into()))
+                .with_response("PriceTomorrow", price_info(r#""tomorrow":[]"#.into())),
+        );
+        let session = TibberSession::builder("token".into())
+            .lenient(true)
+            .build_with_transport(transport.clone());
+        let mut store = Store::open_in_memory()
+            .unwrap()
+            .backfill(Duration::hours(6));
+        let consumption_requests = || -> Vec<String> {
+            transport
+                .requests()
+                .into_iter()
+                .filter(|r| r.contains(r#""operationName":"ConsumptionRange""#))
+                .collect()
+        };
+
+        let report = store.sync(&session, &home).unwrap();
+        assert_eq!(report.consumption, 5);
+        assert_eq!(
+            after(&consumption_requests()[0]),
+            paging::cursor_before(h(6))
+        );
+
+        // The hour without cost is fetched again, along with the new hour
+        let report = store.sync(&session, &home).unwrap();
+        assert_eq!(report.consumption, 4);
+        let requests = consumption_requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(after(&requests[1]), paging::cursor_before(h(4)));
+
+        let stored = store.consumption(&home, h(6), now).unwrap();
+        let from: Vec<_> = stored.iter().map(|c| c.from).collect();
+        assert_eq!(from, (1..=6).rev().map(h).collect::<Vec<_>>());
+        assert_eq!(stored[2].cost, Some(2.0));
+    }
+}
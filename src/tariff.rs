@@ -0,0 +1,439 @@
+//! Grid tariffs, for the all-in cost of electricity.
+//!
+//! [`PriceInfo::total`] covers energy and tax from Tibber, while the grid
+//! company adds an energy fee pr kWh that depends on the time of use, a fixed
+//! monthly fee and a capacity fee, see [`capacity_tariff`]. A [`Tariffs`]
+//! file describes this for each grid company, named as in
+//! [`MeteringPointData::grid_company`]:
+//!
+//! ```toml
+//! [Elvia]
+//! fixed_monthly = 0.0
+//!
+//! # Weekdays 06-22, the first matching fee applies
+//! [[Elvia.energy]]
+//! price = 0.4025
+//! hours = [6, 22]
+//! days = "weekdays"
+//!
+//! [[Elvia.energy]]
+//! price = 0.3025
+//!
+//! [[Elvia.capacity]]
+//! from_kw = 0.0
+//! monthly_price = 155.0
+//!
+//! [[Elvia.capacity]]
+//! from_kw = 2.0
+//! monthly_price = 250.0
+//! ```
+//!
+//! Fees are given in the currency of the prices, including VAT and any
+//! consumption taxes, and times are taken in the time zone of the data.
+//! Holidays are not known, so they count as the weekday they fall on.
+use crate::capacity_tariff::{Step, StepTable};
+use crate::*;
+use chrono::{Datelike, Duration, Months, NaiveDate, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Days an [`EnergyFee`] applies to
+pub enum Days {
+    /// Every day
+    #[default]
+    All,
+    /// Monday to Friday
+    Weekdays,
+    /// Saturday and Sunday
+    Weekends,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Grid energy fee for some hours, days and months
+pub struct EnergyFee {
+    /// Fee pr kWh
+    pub price: f64,
+    /// First hour and the hour after the last, e.g. `[22, 6]` for nights.
+    /// All hours if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hours: Option<[u32; 2]>,
+    /// Days it applies to
+    #[serde(default)]
+    pub days: Days,
+    /// Months it applies to, 1 to 12. All months if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub months: Vec<u32>,
+}
+
+impl EnergyFee {
+    /// Whether the fee applies to an interval starting at `at`
+    pub fn applies(&self, at: DateTime<FixedOffset>) -> bool {
+        let hour = at.hour();
+        let hours = match self.hours {
+            Some([first, end]) if first < end => first <= hour && hour < end,
+            Some([first, end]) if first > end => first <= hour || hour < end,
+            _ => true,
+        };
+        let weekend = matches!(at.weekday(), Weekday::Sat | Weekday::Sun);
+        let days = match self.days {
+            Days::All => true,
+            Days::Weekdays => !weekend,
+            Days::Weekends => weekend,
+        };
+        hours && days && (self.months.is_empty() || self.months.contains(&at.month()))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The fees of a grid company
+pub struct Tariff {
+    /// Energy fees, the first that applies is used
+    #[serde(default)]
+    pub energy: Vec<EnergyFee>,
+    /// Fixed fee pr month
+    #[serde(default)]
+    pub fixed_monthly: f64,
+    /// Capacity steps, see [`capacity_tariff`]
+    #[serde(default)]
+    pub capacity: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A price with the grid energy fee added
+pub struct TariffPrice {
+    /// The price from Tibber
+    pub price: PriceInfo,
+    /// Grid energy fee pr kWh
+    pub grid: f64,
+    /// All-in price pr kWh
+    pub total: f64,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The cost of an interval of consumption, including the grid fees
+pub struct TariffCost {
+    /// The consumption from Tibber
+    pub consumption: Consumption,
+    /// Grid energy fee for the interval
+    pub grid: f64,
+    /// Share of the fixed monthly fee
+    pub fixed: f64,
+    /// Share of the monthly capacity fee
+    pub capacity: f64,
+    /// All-in cost of the interval, None when the energy cost is missing
+    pub total: Option<f64>,
+}
+
+impl Tariff {
+    /// Grid energy fee pr kWh for an interval starting at `at`, zero if no
+    /// fee applies
+    pub fn energy_fee(&self, at: DateTime<FixedOffset>) -> f64 {
+        self.energy
+            .iter()
+            .find(|fee| fee.applies(at))
+            .map_or(0.0, |fee| fee.price)
+    }
+
+    /// The capacity steps as a table
+    pub fn steps(&self) -> StepTable {
+        StepTable::new(self.capacity.clone())
+    }
+
+    /// Capacity fee for the month `year`-`month`, from hourly `consumption`
+    pub fn capacity_fee(&self, consumption: &[Consumption], year: i32, month: u32) -> f64 {
+        self.steps()
+            .status(consumption, year, month)
+            .map_or(0.0, |s| s.monthly_price)
+    }
+
+    /// All-in price pr kWh for each interval of `prices`. Monthly fees don't
+    /// depend on when energy is used, so they are left out.
+    pub fn prices(&self, prices: &[PriceInfo]) -> Vec<TariffPrice> {
+        prices
+            .iter()
+            .map(|p| {
+                let grid = self.energy_fee(p.starts_at);
+                TariffPrice {
+                    price: p.clone(),
+                    grid,
+                    total: p.total + grid,
+                }
+            })
+            .collect()
+    }
+
+    /// Copy of `prices` with the grid energy fee included in
+    /// [`PriceInfo::total`], e.g. for the [`planner`]
+    pub fn all_in(&self, prices: &[PriceInfo]) -> Vec<PriceInfo> {
+        self.prices(prices)
+            .into_iter()
+            .map(|p| PriceInfo {
+                total: p.total,
+                ..p.price
+            })
+            .collect()
+    }
+
+    /// All-in cost of each interval of `consumption`. The monthly fees are
+    /// shared by the length of the intervals, and the capacity step of each
+    /// month is found from `consumption` itself, so it should be hourly and
+    /// cover whole months.
+    pub fn costs(&self, consumption: &[Consumption]) -> Vec<TariffCost> {
+        let mut monthly: HashMap<(i32, u32), f64> = HashMap::new();
+        consumption
+            .iter()
+            .map(|c| {
+                let day = c.from.date_naive();
+                let capacity = *monthly
+                    .entry((day.year(), day.month()))
+                    .or_insert_with(|| self.capacity_fee(consumption, day.year(), day.month()));
+                let share = share(c, day);
+                let kwh = match c.energy {
+                    EnergyUnits::kWh(e) => e,
+                    EnergyUnits::None => 0.0,
+                };
+                let grid = kwh * self.energy_fee(c.from);
+                let fixed = self.fixed_monthly * share;
+                let capacity = capacity * share;
+                TariffCost {
+                    consumption: c.clone(),
+                    grid,
+                    fixed,
+                    capacity,
+                    total: c.cost.map(|cost| cost + grid + fixed + capacity),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Part of the month starting on `day` covered by the interval
+fn share(c: &Consumption, day: NaiveDate) -> f64 {
+    let first = day.with_day(1).unwrap_or(day);
+    let month = match first.checked_add_months(Months::new(1)) {
+        Some(next) => next - first,
+        None => Duration::days(30),
+    };
+    (c.to - c.from).num_seconds() as f64 / month.num_seconds() as f64
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+/// Tariffs by grid company
+pub struct Tariffs {
+    companies: HashMap<String, Tariff>,
+}
+
+impl Tariffs {
+    /// Read tariffs from JSON, an object with a tariff for each grid company
+    pub fn from_json(text: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Read tariffs from TOML, a table with a tariff for each grid company
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Add or replace the tariff of a grid company
+    pub fn insert(&mut self, company: impl Into<String>, tariff: Tariff) {
+        self.companies.insert(company.into(), tariff);
+    }
+
+    /// Tariff of a grid company, ignoring case
+    pub fn get(&self, company: &str) -> Option<&Tariff> {
+        self.companies.get(company).or_else(|| {
+            self.companies
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(company))
+                .map(|(_, tariff)| tariff)
+        })
+    }
+
+    /// Tariff of the grid company of a home
+    pub fn for_home(&self, home: &House) -> Option<&Tariff> {
+        let company = home.metering_point_data.as_ref()?.grid_company.as_ref()?;
+        self.get(company)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    fn hour(from: &str, kwh: f64, cost: Option<f64>) -> Consumption {
+        let from = at(from);
+        Consumption {
+            from,
+            to: from + Duration::hours(1),
+            cost,
+            total_cost: cost,
+            unit_cost: None,
+            unit_price: Some(1.0),
+            unit_price_vat: None,
+            currency: Some("NOK".into()),
+            energy: EnergyUnits::kWh(kwh),
+        }
+    }
+
+    const JSON: &str = r#"{
+        "Elvia": {
+            "fixed_monthly": 310.0,
+            "energy": [
+                {"price": 0.4, "hours": [6, 22], "days": "weekdays"},
+                {"price": 0.1, "hours": [22, 6], "months": [1, 2, 3]},
+                {"price": 0.3}
+            ],
+            "capacity": [
+                {"from_kw": 2.0, "monthly_price": 250.0},
+                {"from_kw": 0.0, "monthly_price": 155.0}
+            ]
+        }
+    }"#;
+
+    /// The TOML example in the module docs
+    #[cfg(feature = "toml")]
+    fn doc_example() -> String {
+        include_str!("tariff.rs")
+            .lines()
+            .skip_while(|l| *l != "//! ```toml")
+            .skip(1)
+            .take_while(|l| *l != "//! ```")
+            .map(|l| format!("{}\n", l.trim_start_matches("//!").trim_start()))
+            .collect()
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn doc_example_loads() {
+        let tariffs = Tariffs::from_toml(&doc_example()).unwrap();
+        let elvia = tariffs.get("Elvia").unwrap();
+        assert_eq!(elvia.energy.len(), 2);
+        assert_eq!(elvia.energy[0].hours, Some([6, 22]));
+        assert_eq!(elvia.energy[0].days, Days::Weekdays);
+        assert_eq!(elvia.capacity.len(), 2);
+        assert_eq!(elvia.energy_fee(at("2024-03-04T08:00:00+01:00")), 0.4025);
+        assert_eq!(elvia.energy_fee(at("2024-03-09T08:00:00+01:00")), 0.3025);
+    }
+
+    #[test]
+    fn json_loads_and_round_trips() {
+        let tariffs = Tariffs::from_json(JSON).unwrap();
+        let elvia = tariffs.get("Elvia").unwrap();
+        assert_eq!(elvia.fixed_monthly, 310.0);
+        assert_eq!(elvia.energy[2].days, Days::All);
+        assert!(elvia.energy[2].months.is_empty());
+        let text = serde_json::to_string(&tariffs).unwrap();
+        assert_eq!(Tariffs::from_json(&text).unwrap(), tariffs);
+        assert!(Tariffs::from_json(r#"{"Elvia": {"energy": 3}}"#).is_err());
+    }
+
+    #[test]
+    fn hours_wrap_around_midnight() {
+        let night = EnergyFee {
+            price: 0.1,
+            hours: Some([22, 6]),
+            days: Days::All,
+            months: Vec::new(),
+        };
+        assert!(night.applies(at("2024-03-04T22:00:00+01:00")));
+        assert!(night.applies(at("2024-03-04T23:00:00+01:00")));
+        assert!(night.applies(at("2024-03-05T05:00:00+01:00")));
+        assert!(!night.applies(at("2024-03-05T06:00:00+01:00")));
+        assert!(!night.applies(at("2024-03-05T21:00:00+01:00")));
+        // Hours are local to the data, not UTC
+        assert!(night.applies(at("2024-03-05T05:30:00+01:00")));
+        assert!(!night.applies(at("2024-03-05T06:30:00+02:00")));
+    }
+
+    #[test]
+    fn days_and_months_filter() {
+        let winter_weekend = EnergyFee {
+            price: 0.2,
+            hours: None,
+            days: Days::Weekends,
+            months: vec![12, 1, 2],
+        };
+        assert!(winter_weekend.applies(at("2024-01-06T12:00:00+01:00")));
+        assert!(winter_weekend.applies(at("2024-01-07T12:00:00+01:00")));
+        assert!(!winter_weekend.applies(at("2024-01-08T12:00:00+01:00")));
+        assert!(!winter_weekend.applies(at("2024-03-09T12:00:00+01:00")));
+
+        let tariff = &Tariffs::from_json(JSON).unwrap().companies["Elvia"];
+        // Weekday, weekend night in winter, and weekend night in summer
+        assert_eq!(tariff.energy_fee(at("2024-03-04T08:00:00+01:00")), 0.4);
+        assert_eq!(tariff.energy_fee(at("2024-03-09T23:00:00+01:00")), 0.1);
+        assert_eq!(tariff.energy_fee(at("2024-06-08T23:00:00+02:00")), 0.3);
+    }
+
+    #[test]
+    fn lookup_ignores_case() {
+        let mut tariffs = Tariffs::from_json(JSON).unwrap();
+        assert!(tariffs.get("ELVIA").is_some());
+        assert!(tariffs.get("Tensio").is_none());
+        // An exact match wins over one that differs in case
+        tariffs.insert("elvia", Tariff::default());
+        assert_eq!(tariffs.get("elvia"), Some(&Tariff::default()));
+        assert_eq!(tariffs.get("Elvia").unwrap().fixed_monthly, 310.0);
+    }
+
+    #[test]
+    fn prices_include_grid_fee() {
+        let tariff = Tariffs::from_json(JSON)
+            .unwrap()
+            .companies
+            .remove("Elvia")
+            .unwrap();
+        let price = PriceInfo {
+            total: 1.0,
+            energy: 0.8,
+            tax: 0.2,
+            starts_at: at("2024-03-04T08:00:00+01:00"),
+            currency: "NOK".into(),
+            level: PriceLevel::Normal,
+        };
+        let annotated = tariff.prices(std::slice::from_ref(&price));
+        assert_eq!(annotated[0].grid, 0.4);
+        assert_eq!(annotated[0].total, 1.4);
+        let all_in = tariff.all_in(&[price]);
+        assert_eq!(all_in[0].total, 1.4);
+        assert_eq!(all_in[0].energy, 0.8);
+    }
+
+    #[test]
+    fn costs_share_monthly_fees() {
+        let tariff = Tariffs::from_json(JSON)
+            .unwrap()
+            .companies
+            .remove("Elvia")
+            .unwrap();
+        // March has 31 days, and peaks of 3 and 1 kW average to the 2 kW step
+        let consumption = [
+            hour("2024-03-04T08:00:00+01:00", 3.0, Some(3.0)),
+            hour("2024-03-05T08:00:00+01:00", 1.0, None),
+        ];
+        assert_eq!(tariff.capacity_fee(&consumption, 2024, 3), 250.0);
+        let costs = tariff.costs(&consumption);
+        let hours = 31.0 * 24.0;
+        assert!((costs[0].grid - 1.2).abs() < 1e-9);
+        assert!((costs[0].fixed - 310.0 / hours).abs() < 1e-9);
+        assert!((costs[0].capacity - 250.0 / hours).abs() < 1e-9);
+        let total = 3.0 + 1.2 + (310.0 + 250.0) / hours;
+        assert!((costs[0].total.unwrap() - total).abs() < 1e-9);
+        assert_eq!(costs[1].total, None);
+
+        // February 2024 has 29 days, and the lower step
+        let february = [hour("2024-02-05T08:00:00+01:00", 1.0, Some(1.0))];
+        let costs = tariff.costs(&february);
+        assert!((costs[0].capacity - 155.0 / (29.0 * 24.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,254 @@
+//! HTTP transports used by the sessions to deliver GraphQL requests
+use crate::*;
+use std::collections::{HashMap, VecDeque};
+
+/// Delivers a serialized GraphQL request and returns the HTTP status and the
+/// JSON response body
+///
+/// Implement this to use a custom HTTP stack with [`TibberSession`], or use
+/// [`FixtureTransport`] to run without network. The body should be returned
+/// for any status, as the API explains failures such as a rejected token in
+/// the GraphQL `errors` of the body.
+pub trait Transport {
+    /// Send `body` authenticated with the API `token`, and return the status
+    /// and response body
+    fn execute(&self, token: &str, body: &str) -> Result<(u16, String), Error>;
+}
+
+/// Async version of [`Transport`], used by [`AsyncTibberSession`]
+#[cfg(feature = "async")]
+pub trait AsyncTransport {
+    /// Send `body` authenticated with the API `token`, and return the status
+    /// and response body
+    fn execute(
+        &self,
+        token: &str,
+        body: &str,
+    ) -> impl std::future::Future<Output = Result<(u16, String), Error>> + Send;
+}
+
+/// Transport used by [`TibberSession::new()`] and [`TibberSessionBuilder::build()`]
+#[cfg(feature = "reqwest")]
+pub type DefaultTransport = ReqwestTransport;
+/// Transport used by [`TibberSession::new()`] and [`TibberSessionBuilder::build()`]
+#[cfg(all(feature = "ureq", not(feature = "reqwest")))]
+pub type DefaultTransport = UreqTransport;
+
+/// Transport based on the blocking reqwest client
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: ::reqwest::blocking::Client,
+    endpoint: String,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// Post requests to `endpoint` using `client`
+    pub fn new(client: ::reqwest::blocking::Client, endpoint: impl Into<String>) -> Self {
+        ReqwestTransport {
+            client,
+            endpoint: endpoint.into(),
+        }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl Transport for ReqwestTransport {
+    fn execute(&self, token: &str, body: &str) -> Result<(u16, String), Error> {
+        let response = self
+            .client
+            .post(self.endpoint.as_str())
+            .bearer_auth(token)
+            .header(::reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_owned())
+            .send()?;
+        let status = response.status().as_u16();
+        Ok((status, response.text()?))
+    }
+}
+
+/// Transport based on the non-blocking reqwest client
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct AsyncReqwestTransport {
+    client: ::reqwest::Client,
+    endpoint: String,
+}
+
+#[cfg(feature = "async")]
+impl AsyncReqwestTransport {
+    /// Post requests to `endpoint` using `client`
+    pub fn new(client: ::reqwest::Client, endpoint: impl Into<String>) -> Self {
+        AsyncReqwestTransport {
+            client,
+            endpoint: endpoint.into(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for AsyncReqwestTransport {
+    async fn execute(&self, token: &str, body: &str) -> Result<(u16, String), Error> {
+        let response = self
+            .client
+            .post(self.endpoint.as_str())
+            .bearer_auth(token)
+            .header(::reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_owned())
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        Ok((status, response.text().await?))
+    }
+}
+
+/// Transport based on an ureq agent
+#[cfg(feature = "ureq")]
+#[derive(Debug, Clone)]
+pub struct UreqTransport {
+    agent: ureq_crate::Agent,
+    endpoint: String,
+}
+
+#[cfg(feature = "ureq")]
+impl UreqTransport {
+    /// Post requests to `endpoint` using `agent`
+    pub fn new(agent: ureq_crate::Agent, endpoint: impl Into<String>) -> Self {
+        UreqTransport {
+            agent,
+            endpoint: endpoint.into(),
+        }
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl Transport for UreqTransport {
+    fn execute(&self, token: &str, body: &str) -> Result<(u16, String), Error> {
+        let response = match self
+            .agent
+            .post(self.endpoint.as_str())
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("Content-Type", "application/json")
+            .send_string(body)
+        {
+            Ok(response) | Err(ureq_crate::Error::Status(_, response)) => response,
+            Err(e) => return Err(e.into()),
+        };
+        let status = response.status();
+        let body = response
+            .into_string()
+            .map_err(|e| Error::Transport(Box::new(e)))?;
+        Ok((status, body))
+    }
+}
+
+/// In-memory transport answering with canned responses, for tests
+///
+/// Responses are registered per GraphQL operation name, e.g. `Viewer`,
+/// `Home` or `PriceToday`. Responses for the same operation are handed out
+/// in the order they were added, and the last one is repeated.
+#[derive(Debug, Default)]
+pub struct FixtureTransport {
+    responses: Mutex<HashMap<String, VecDeque<(u16, String)>>>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl FixtureTransport {
+    /// Create a transport without any responses
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a JSON response body for the operation `operation`
+    pub fn with_response(self, operation: &str, response: impl Into<String>) -> Self {
+        self.add_response(operation, response);
+        self
+    }
+
+    /// Add a JSON response body for the operation `operation`
+    pub fn add_response(&self, operation: &str, response: impl Into<String>) {
+        self.add_http_response(operation, 200, response);
+    }
+
+    /// Add a JSON response body with HTTP status `status` for the operation
+    /// `operation`
+    pub fn with_http_response(
+        self,
+        operation: &str,
+        status: u16,
+        response: impl Into<String>,
+    ) -> Self {
+        self.add_http_response(operation, status, response);
+        self
+    }
+
+    /// Add a JSON response body with HTTP status `status` for the operation
+    /// `operation`
+    pub fn add_http_response(&self, operation: &str, status: u16, response: impl Into<String>) {
+        self.responses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(operation.to_owned())
+            .or_default()
+            .push_back((status, response.into()));
+    }
+
+    /// All request bodies received so far, oldest first
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn respond(&self, body: &str) -> Result<(u16, String), Error> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(body.to_owned());
+        let request: serde_json::Value = serde_json::from_str(body)?;
+        let operation = request["operationName"].as_str().unwrap_or_default();
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let queue = responses.get_mut(operation).ok_or_else(|| {
+            Error::Transport(format!("no fixture for operation {:?}", operation).into())
+        })?;
+        match queue.len() {
+            0 => Err(Error::Transport(
+                format!("no fixture for operation {:?}", operation).into(),
+            )),
+            1 => Ok(queue[0].clone()),
+            _ => Ok(queue.pop_front().unwrap_or_default()),
+        }
+    }
+}
+
+impl Transport for FixtureTransport {
+    fn execute(&self, _token: &str, body: &str) -> Result<(u16, String), Error> {
+        self.respond(body)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for FixtureTransport {
+    async fn execute(&self, _token: &str, body: &str) -> Result<(u16, String), Error> {
+        self.respond(body)
+    }
+}
+
+impl<T: Transport> Transport for Arc<T> {
+    fn execute(&self, token: &str, body: &str) -> Result<(u16, String), Error> {
+        (**self).execute(token, body)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: AsyncTransport + Sync + Send> AsyncTransport for Arc<T> {
+    fn execute(
+        &self,
+        token: &str,
+        body: &str,
+    ) -> impl std::future::Future<Output = Result<(u16, String), Error>> + Send {
+        (**self).execute(token, body)
+    }
+}
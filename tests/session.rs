@@ -0,0 +1,523 @@
+use std::sync::Arc;
+use tibber::*;
+
+fn session(transport: FixtureTransport) -> TibberSession<FixtureTransport> {
+    TibberSession::with_transport("token".into(), transport)
+}
+
+const VIEWER: &str = r#"{"data":{"viewer":{"login":"user@example.com","userId":"u1","name":"User","accountType":["tibber"],"homes":[{"id":"home"}]}}}"#;
+
+/// Home ids can only be had from the API
+fn home_id() -> HomeId {
+    let conn = session(FixtureTransport::new().with_response("Viewer", VIEWER));
+    conn.get_user().unwrap().homes.remove(0)
+}
+
+#[test]
+fn rejected_token_keeps_graphql_errors() {
+    let body = r#"{"data":null,"errors":[{"message":"invalid token","extensions":{"code":"UNAUTHENTICATED"}}]}"#;
+    let conn = session(FixtureTransport::new().with_http_response("Viewer", 400, body));
+    let err = conn.get_user().unwrap_err();
+    assert!(err.is_auth(), "{:?}", err);
+    match err {
+        Error::GraphQL(errors) => {
+            assert_eq!(errors[0].message, "invalid token");
+            assert_eq!(errors[0].code.as_deref(), Some("UNAUTHENTICATED"));
+        }
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn failed_status_without_errors_is_http() {
+    let conn = session(FixtureTransport::new().with_http_response("Viewer", 502, "Bad gateway"));
+    let err = conn.get_user().unwrap_err();
+    assert!(matches!(err, Error::Http(502)), "{:?}", err);
+    assert!(err.is_transient());
+}
+
+fn current_price(total: f64) -> String {
+    let now = chrono::Utc::now();
+    let starts_at = now - chrono::Duration::seconds(now.timestamp() % 900);
+    format!(
+        r#"{{"data":{{"viewer":{{"home":{{"currentSubscription":{{"priceInfo":{{"current":{{"total":{},"energy":{},"tax":0.0,"startsAt":"{}","currency":"NOK","level":"NORMAL"}}}}}}}}}}}}}}"#,
+        total,
+        total,
+        starts_at.to_rfc3339()
+    )
+}
+
+#[test]
+fn cache_is_kept_apart_by_endpoint_and_token() {
+    let dir = std::env::temp_dir().join(format!("tibber-cache-test-{}", std::process::id()));
+    let home = home_id();
+    let cached = |token: &str, endpoint: &str, total: f64| {
+        TibberSession::builder(token.into())
+            .endpoint(endpoint)
+            .cache_dir(&dir)
+            .build_with_transport(
+                FixtureTransport::new().with_response("Price", current_price(total)),
+            )
+            .get_current_price(&home)
+            .unwrap()
+            .total
+    };
+    assert_eq!(
+        cached("token", "https://api.tibber.com/v1-beta/gql", 1.0),
+        1.0
+    );
+    assert_eq!(
+        cached("token", "https://api.tibber.com/v1-beta/gql", 2.0),
+        1.0
+    );
+    assert_eq!(cached("token", "http://localhost:8080/gql", 3.0), 3.0);
+    assert_eq!(
+        cached("other", "https://api.tibber.com/v1-beta/gql", 4.0),
+        4.0
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_session_reads_and_writes_cache() {
+    let dir = std::env::temp_dir().join(format!("tibber-async-cache-test-{}", std::process::id()));
+    let home = home_id();
+    let cached = |total: f64| {
+        TibberSession::builder("token".into())
+            .cache_dir(&dir)
+            .build_async_with_transport(
+                FixtureTransport::new().with_response("Price", current_price(total)),
+            )
+    };
+    let first = cached(1.0).get_current_price(&home).await.unwrap();
+    let second = cached(2.0).get_current_price(&home).await.unwrap();
+    assert_eq!((first.total, second.total), (1.0, 1.0));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn hour(h: i64) -> chrono::DateTime<chrono::FixedOffset> {
+    chrono::DateTime::parse_from_rfc3339("2024-03-01T00:00:00+01:00").unwrap()
+        + chrono::Duration::hours(h)
+}
+
+/// A page of a connection, with cursors named by the first and last hour
+fn page(
+    hours: std::ops::Range<i64>,
+    node: fn(i64) -> String,
+    next: bool,
+    previous: bool,
+) -> String {
+    let nodes: Vec<String> = hours.clone().map(node).collect();
+    format!(
+        r#"{{"startCursor":"c{}","endCursor":"c{}","hasNextPage":{},"hasPreviousPage":{}}}, "nodes":[{}]"#,
+        hours.start,
+        hours.end - 1,
+        next,
+        previous,
+        nodes.join(",")
+    )
+}
+
+fn consumption_page(hours: std::ops::Range<i64>, next: bool, previous: bool) -> String {
+    let node = |h: i64| {
+        format!(
+            r#"{{"node":{{"from":"{}","to":"{}","cost":{},"totalCost":null,"unitCost":null,"unitPrice":1.0,"unitPriceVAT":0.2,"consumption":{},"consumptionUnit":"kWh","currency":"NOK"}}}}"#,
+            hour(h).to_rfc3339(),
+            hour(h + 1).to_rfc3339(),
+            h,
+            h
+        )
+    };
+    let page = page(hours, node, next, previous).replace("\"nodes\"", "\"edges\"");
+    format!(
+        r#"{{"data":{{"viewer":{{"home":{{"consumption":{{"pageInfo":{}}}}}}}}}}}"#,
+        page
+    )
+}
+
+fn price_page(hours: std::ops::Range<i64>, next: bool) -> String {
+    let node = |h: i64| {
+        format!(
+            r#"{{"total":{},"energy":{},"tax":0.0,"startsAt":"{}","currency":"NOK","level":"NORMAL"}}"#,
+            h,
+            h,
+            hour(h).to_rfc3339()
+        )
+    };
+    format!(
+        r#"{{"data":{{"viewer":{{"home":{{"currentSubscription":{{"priceInfo":{{"range":{{"pageInfo":{}}}}}}}}}}}}}}}"#,
+        page(hours, node, next, true)
+    )
+}
+
+fn variables(request: &str) -> serde_json::Value {
+    serde_json::from_str::<serde_json::Value>(request).unwrap()["variables"].clone()
+}
+
+#[test]
+fn last_span_pages_backwards() {
+    // Asked for the last 5 hours, the API only gives 3 at a time, and the
+    // second page overlaps the first
+    let transport = Arc::new(
+        FixtureTransport::new()
+            .with_response("ConsumptionRange", consumption_page(7..10, false, true))
+            .with_response("ConsumptionRange", consumption_page(3..8, false, true)),
+    );
+    let conn = TibberSession::with_transport("token".into(), transport.clone());
+    let nodes = conn
+        .get_consumption_range(&home_id(), TimeResolution::Hourly, Span::Last(5), false)
+        .unwrap();
+    let from: Vec<_> = nodes.iter().map(|c| c.from).collect();
+    assert_eq!(from, (5..10).map(hour).collect::<Vec<_>>());
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(variables(&requests[0])["last"], 5);
+    assert_eq!(variables(&requests[0])["before"], serde_json::Value::Null);
+    assert_eq!(variables(&requests[1])["last"], 2);
+    assert_eq!(variables(&requests[1])["before"], "c7");
+}
+
+#[test]
+fn between_span_pages_forwards_until_the_end() {
+    let transport = Arc::new(
+        FixtureTransport::new()
+            .with_response("PriceRange", price_page(0..3, true))
+            .with_response("PriceRange", price_page(2..6, true)),
+    );
+    let conn = TibberSession::with_transport("token".into(), transport.clone());
+    let prices = conn
+        .get_price_range(
+            &home_id(),
+            PriceResolution::Hourly,
+            Span::Between(hour(1), hour(4)),
+        )
+        .unwrap();
+    let starts: Vec<_> = prices.iter().map(|p| p.starts_at).collect();
+    assert_eq!(starts, (1..4).map(hour).collect::<Vec<_>>());
+
+    // The second page reaches past the end, so there is no third request
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 2);
+    // Base64 of the second before the start, "2024-03-01T00:59:59.000+01:00"
+    assert_eq!(
+        variables(&requests[0])["after"],
+        "MjAyNC0wMy0wMVQwMDo1OTo1OS4wMDArMDE6MDA="
+    );
+    assert_eq!(variables(&requests[1])["after"], "c2");
+}
+
+#[test]
+fn since_span_starts_after_a_time_cursor() {
+    let transport = Arc::new(
+        FixtureTransport::new()
+            .with_response("ConsumptionRange", consumption_page(2..5, false, true)),
+    );
+    let conn = TibberSession::with_transport("token".into(), transport.clone());
+    let nodes = conn
+        .get_consumption_range(
+            &home_id(),
+            TimeResolution::Hourly,
+            Span::Since(hour(2)),
+            false,
+        )
+        .unwrap();
+    let from: Vec<_> = nodes.iter().map(|c| c.from).collect();
+    assert_eq!(from, (2..5).map(hour).collect::<Vec<_>>());
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    // Base64 of "2024-03-01T01:59:59.000+01:00"
+    assert_eq!(
+        variables(&requests[0])["after"],
+        "MjAyNC0wMy0wMVQwMTo1OTo1OS4wMDArMDE6MDA="
+    );
+    assert_eq!(variables(&requests[0])["first"], 200);
+}
+
+#[test]
+fn partial_mode_returns_data_with_errors() {
+    let error = r#"{"message":"home not found","path":["viewer","homes",1],"extensions":{"code":"NOT_FOUND"}}"#;
+    let complete = format!(
+        r#"{{"data":{{"viewer":{{"login":"user@example.com","userId":"u1","name":"User","accountType":["tibber"],"homes":[{{"id":"home"}},null]}}}},"errors":[{}]}}"#,
+        error
+    );
+    let conn = session(FixtureTransport::new().with_response("Viewer", complete));
+    assert!(matches!(conn.get_user(), Err(Error::GraphQL(_))));
+
+    let user = conn.partial(|s| s.get_user()).unwrap();
+    assert_eq!(user.data.homes.len(), 1);
+    assert_eq!(user.errors.len(), 1);
+    assert_eq!(user.errors[0].code.as_deref(), Some("NOT_FOUND"));
+    assert_eq!(user.errors[0].path, ["viewer", "homes", "1"]);
+
+    // A field the result can't do without is reported as the errors
+    let missing = format!(
+        r#"{{"data":{{"viewer":{{"login":"user@example.com","userId":"u1","name":null,"accountType":[],"homes":[]}}}},"errors":[{}]}}"#,
+        error
+    );
+    let conn = session(FixtureTransport::new().with_response("Viewer", missing));
+    match conn.partial(|s| s.get_user()) {
+        Err(Error::GraphQL(errors)) => assert_eq!(errors[0].message, "home not found"),
+        r => panic!("unexpected result {:?}", r.map(|p| p.errors)),
+    }
+}
+
+#[test]
+fn update_home_converts_the_returned_home() {
+    let body = r#"{"data":{"updateHome":{"timeZone":"Europe/Oslo","appNickname":"Cabin","appAvatar":"COTTAGE","size":60,"type":"COTTAGE","numberOfResidents":2,"primaryHeatingSource":null,"hasVentilationSystem":false,"mainFuseSize":25,"address":null,"owner":null,"meteringPointData":{"consumptionEan":null,"gridCompany":null,"gridAreaCode":null,"priceAreaCode":"NO1","productionEan":null,"energyTaxType":null,"vatType":null,"estimatedAnnualConsumption":-1},"currentSubscription":null,"features":null}}}"#;
+    let transport = Arc::new(FixtureTransport::new().with_response("UpdateHome", body));
+    let conn = TibberSession::with_transport("token".into(), transport.clone());
+    let house = conn
+        .update_home(&home_id(), HomeUpdate::new().app_nickname("Cabin").size(60))
+        .unwrap();
+    assert_eq!(house.app_nickname.as_deref(), Some("Cabin"));
+    assert_eq!(house.app_avatar, HomeAvatar::Cottage);
+    assert!(matches!(house.house_type, HouseType::Cottage));
+    assert!(matches!(
+        house.primary_heating_source,
+        HeatingSource::Unknown
+    ));
+    assert_eq!(house.size, Some(60));
+    let mpd = house.metering_point_data.unwrap();
+    assert_eq!(mpd.price_area_code.as_deref(), Some("NO1"));
+    assert_eq!(mpd.estimated_annual_consumption, None);
+
+    // The home is not fetched again after the mutation
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    let input = &variables(&requests[0])["input"];
+    assert_eq!(input["appNickname"], "Cabin");
+    assert_eq!(input["size"], 60);
+    assert!(input.get("mainFuseSize").is_none());
+}
+
+#[test]
+fn invalid_home_updates_send_no_request() {
+    let home = home_id();
+    let updates = [
+        HomeUpdate::new(),
+        HomeUpdate::new().primary_heating_source(HeatingSource::Unknown),
+        HomeUpdate::new().size(i32::MAX as u32 + 1),
+        HomeUpdate::new().number_of_residents(u32::MAX),
+        HomeUpdate::new()
+            .app_nickname("Cabin")
+            .main_fuse_size(i32::MAX as u32 + 1),
+    ];
+    for update in updates {
+        let transport = Arc::new(FixtureTransport::new());
+        let conn = TibberSession::with_transport("token".into(), transport.clone());
+        let err = conn.update_home(&home, update).unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)), "{:?}", err);
+        assert!(transport.requests().is_empty());
+    }
+}
+
+#[test]
+fn invalid_meter_readings_send_no_request() {
+    let home = home_id();
+    let now = chrono::Utc::now().fixed_offset();
+    let readings = [
+        (now + chrono::Duration::hours(1), 1000),
+        (now, i32::MAX as u32 + 1),
+    ];
+    for (time, reading) in readings {
+        let transport = Arc::new(FixtureTransport::new());
+        let conn = TibberSession::with_transport("token".into(), transport.clone());
+        let err = conn.send_meter_reading(&home, time, reading).unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)), "{:?}", err);
+        assert!(transport.requests().is_empty());
+    }
+}
+
+#[test]
+fn empty_push_notification_sends_no_request() {
+    for message in ["", "  \n"] {
+        let transport = Arc::new(FixtureTransport::new());
+        let conn = TibberSession::with_transport("token".into(), transport.clone());
+        let err = conn
+            .send_push_notification("Title", message, AppScreen::Home)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)), "{:?}", err);
+        assert!(transport.requests().is_empty());
+    }
+}
+
+#[test]
+fn subscriptions_parse_dates_and_status() {
+    let subscription = |id: &str, from: &str, to: &str, status: &str| {
+        format!(
+            r#"{{"id":"{}","subscriber":{{"id":"o1","firstName":"Ola","isCompany":false,"name":"Ola Nordmann","middleName":null,"lastName":"Nordmann","organizationNo":null,"language":"nb-NO","contactInfo":null,"address":null}},"validFrom":{},"validTo":{},"status":{}}}"#,
+            id, from, to, status
+        )
+    };
+    let body = format!(
+        r#"{{"data":{{"viewer":{{"home":{{"subscriptions":[{},{},{},{},{}]}}}}}}}}"#,
+        subscription(
+            "s1",
+            r#""2023-01-01T00:00:00+01:00""#,
+            r#""2024-01-01T00:00:00+01:00""#,
+            r#""ended""#
+        ),
+        subscription(
+            "s2",
+            r#""2024-01-01T00:00:00+01:00""#,
+            "null",
+            r#""Running""#
+        ),
+        subscription("s3", "null", "null", r#""awaiting time restriction""#),
+        subscription("s4", "null", "null", r#""paused""#),
+        subscription("s5", "null", "null", "null"),
+    );
+    let conn = session(FixtureTransport::new().with_response("Subscriptions", body));
+    let subscriptions = conn.get_subscriptions(&home_id()).unwrap();
+    let status: Vec<_> = subscriptions.iter().map(|s| s.status.clone()).collect();
+    assert_eq!(
+        status,
+        [
+            SubscriptionStatus::Ended,
+            SubscriptionStatus::Running,
+            SubscriptionStatus::Awaiting("awaiting time restriction".into()),
+            SubscriptionStatus::Other("paused".into()),
+            SubscriptionStatus::Unknown,
+        ]
+    );
+
+    let running = &subscriptions[1];
+    assert_eq!(
+        running.valid_from,
+        Some(hour(0) - chrono::Duration::days(60))
+    );
+    assert_eq!(running.valid_to, None);
+    assert!(running.is_active_at(&hour(0)));
+    assert!(!subscriptions[0].is_active_at(&hour(0)));
+    assert_eq!(
+        subscriptions[0].valid_to.unwrap().to_rfc3339(),
+        "2024-01-01T00:00:00+01:00"
+    );
+
+    let bad_date = format!(
+        r#"{{"data":{{"viewer":{{"home":{{"subscriptions":[{}]}}}}}}}}"#,
+        subscription("s1", r#""yesterday""#, "null", r#""running""#)
+    );
+    let conn = session(FixtureTransport::new().with_response("Subscriptions", bad_date));
+    let err = conn.get_subscriptions(&home_id()).unwrap_err();
+    assert!(matches!(err, Error::Timestamp { .. }), "{:?}", err);
+}
+
+/// Consumption history of two hours, where the cost of the second is not
+/// known yet
+fn consumption_history() -> String {
+    let node = |h: i64, cost: &str| {
+        format!(
+            r#"{{"from":"{}","to":"{}","cost":{},"totalCost":{},"unitCost":0.5,"unitPrice":1.0,"unitPriceVAT":0.2,"consumption":2.0,"consumptionUnit":"kWh","currency":"NOK"}}"#,
+            hour(h).to_rfc3339(),
+            hour(h + 1).to_rfc3339(),
+            cost,
+            cost
+        )
+    };
+    format!(
+        r#"{{"data":{{"viewer":{{"home":{{"consumption":{{"pageInfo":{{"count":2,"currency":"NOK","totalCost":2.0,"energyCost":2.0,"totalConsumption":4.0,"filtered":1}},"nodes":[{},{}]}}}}}}}}}}"#,
+        node(0, "2.0"),
+        node(1, "null")
+    )
+}
+
+#[test]
+fn lenient_mode_keeps_hours_without_cost() {
+    let strict =
+        session(FixtureTransport::new().with_response("ConsumptionHistory", consumption_history()));
+    let nodes = strict
+        .get_consumption(&home_id(), TimeResolution::Hourly, 2)
+        .unwrap();
+    assert_eq!(nodes.len(), 1);
+    let node = &nodes[0];
+    assert_eq!((node.from, node.to), (hour(0), hour(1)));
+    assert_eq!(node.cost, Some(2.0));
+    assert_eq!(node.total_cost, Some(2.0));
+    assert_eq!(node.unit_cost, Some(0.5));
+    assert_eq!(node.currency.as_deref(), Some("NOK"));
+    assert!(matches!(node.energy, EnergyUnits::kWh(e) if e == 2.0));
+
+    let lenient = TibberSession::builder("token".into())
+        .lenient(true)
+        .build_with_transport(
+            FixtureTransport::new().with_response("ConsumptionHistory", consumption_history()),
+        );
+    let nodes = lenient
+        .get_consumption(&home_id(), TimeResolution::Hourly, 2)
+        .unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[1].from, hour(1));
+    assert_eq!(nodes[1].cost, None);
+    assert_eq!(nodes[1].total_cost, None);
+    assert_eq!(nodes[1].unit_price, Some(1.0));
+}
+
+#[test]
+fn summaries_keep_the_page_totals() {
+    let conn =
+        session(FixtureTransport::new().with_response("ConsumptionHistory", consumption_history()));
+    let summary = conn
+        .get_consumption_summary(&home_id(), TimeResolution::Hourly, 2)
+        .unwrap();
+    // The totals are taken from the API, not summed from the nodes left
+    assert_eq!(summary.nodes.len(), 1);
+    assert_eq!(summary.total_cost, Some(2.0));
+    assert_eq!(summary.total_consumption, Some(4.0));
+    assert_eq!(summary.currency.as_deref(), Some("NOK"));
+    assert_eq!(summary.count, Some(2));
+    assert_eq!(summary.filtered, 1);
+
+    let production = format!(
+        r#"{{"data":{{"viewer":{{"home":{{"production":{{"pageInfo":{{"count":-1,"currency":"NOK","totalProfit":0.7,"totalProduction":3.5,"filtered":0}},"nodes":[{{"from":"{}","to":"{}","profit":0.7,"unitPrice":0.2,"unitPriceVAT":0.04,"production":3.5,"productionUnit":"kWh","currency":"NOK"}}]}}}}}}}}}}"#,
+        hour(0).to_rfc3339(),
+        hour(1).to_rfc3339()
+    );
+    let conn = session(FixtureTransport::new().with_response("ProductionHistory", production));
+    let summary = conn
+        .get_production_summary(&home_id(), TimeResolution::Hourly, 1)
+        .unwrap();
+    assert_eq!(summary.nodes.len(), 1);
+    assert_eq!(summary.total_profit, Some(0.7));
+    assert_eq!(summary.total_production, Some(3.5));
+    assert_eq!(summary.count, None);
+    assert_eq!(summary.filtered, 0);
+}
+
+#[test]
+fn price_rating_keeps_thresholds_and_levels() {
+    let rating = |level: &str| {
+        format!(
+            r#"{{"minEnergy":0.5,"maxEnergy":1.5,"minTotal":0.7,"maxTotal":2.0,"currency":"NOK","entries":[{{"time":"{}","energy":0.5,"total":0.7,"tax":0.2,"difference":-30.0,"level":"LOW"}},{{"time":"{}","energy":1.5,"total":2.0,"tax":0.5,"difference":40.0,"level":"{}"}}]}}"#,
+            hour(0).to_rfc3339(),
+            hour(1).to_rfc3339(),
+            level
+        )
+    };
+    let body = format!(
+        r#"{{"data":{{"viewer":{{"home":{{"currentSubscription":{{"priceRating":{{"thresholdPercentages":{{"high":20.0,"low":10.0}},"hourly":{},"daily":{},"monthly":{}}}}}}}}}}}}}"#,
+        rating("HIGH"),
+        rating("NORMAL"),
+        rating("EXTREME")
+    );
+    let conn = session(FixtureTransport::new().with_response("PriceRatings", body));
+    let rating = conn.get_price_rating(&home_id()).unwrap();
+    assert_eq!((rating.threshold_high, rating.threshold_low), (20.0, 10.0));
+
+    let hourly = &rating.hourly;
+    assert_eq!((hourly.min_total, hourly.max_total), (0.7, 2.0));
+    assert_eq!(hourly.currency, "NOK");
+    let times: Vec<_> = hourly.entries.iter().map(|e| e.time).collect();
+    assert_eq!(times, [hour(0), hour(1)]);
+    assert_eq!(hourly.entries[0].level, PriceRatingLevel::Low);
+    assert_eq!(hourly.entries[0].difference, -30.0);
+    assert_eq!(hourly.entries[1].level, PriceRatingLevel::High);
+    assert_eq!(rating.daily.entries[1].level, PriceRatingLevel::Normal);
+    assert_eq!(
+        rating.monthly.entries[1].level,
+        PriceRatingLevel::Other("EXTREME".into())
+    );
+}